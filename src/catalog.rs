@@ -0,0 +1,313 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+};
+
+use memmap2::Mmap;
+use rkyv::AlignedVec;
+
+use crate::{
+    consts::ARCHIVE_HEADER_LEN,
+    data::{
+        ArchivedAssociation, ArchivedIndex, Association, Efo, GeneResolution, GenomicRegion, Hgnc,
+        Index, TermId,
+    },
+    error::Result,
+    files::{
+        archived_associations, archived_index, load_efo, load_hgnc, map_associations, map_index,
+    },
+    query::{self, EfoMatch, Filter, GeneMatches, RegionMatches, TraitMatches, VariantMatches},
+};
+
+/// The bytes of an archive, header included.
+enum Storage {
+    Mapped(Mmap),
+    Owned(AlignedVec),
+}
+
+impl Storage {
+    /// Archives `value` in memory behind a blank header, so it can be read
+    /// like the files.
+    fn archive<T: rkyv::Serialize<rkyv::ser::serializers::AllocSerializer<256>>>(
+        value: &T,
+    ) -> Self {
+        let archive = rkyv::to_bytes::<_, 256>(value).expect("serializing to memory is infallible");
+        let mut bytes = AlignedVec::with_capacity(ARCHIVE_HEADER_LEN + archive.len());
+        bytes.extend_from_slice(&[0; ARCHIVE_HEADER_LEN]);
+        bytes.extend_from_slice(&archive);
+        Self::Owned(bytes)
+    }
+
+    #[inline]
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Self::Mapped(mmap) => &mmap[..],
+            Self::Owned(bytes) => &bytes[..],
+        }
+    }
+}
+
+/// The GWAS Catalog associations and EFO terms, loaded once and queried any
+/// number of times. Associations are read in place from the memory-mapped
+/// archive rather than deserialized, and trait, gene and variant lookups go
+/// through the persisted index instead of scanning every association.
+pub struct Catalog {
+    associations: Storage,
+    index: Storage,
+    efos: Vec<Efo>,
+    // id -> index into `efos`
+    by_id: HashMap<TermId, usize>,
+    // `None` unless an HGNC complete set has been imported
+    hgnc: Option<Hgnc>,
+}
+
+impl Catalog {
+    /// Builds a catalog from owned associations, archiving them and their
+    /// index in memory.
+    pub fn new(associations: Vec<Association>, efos: Vec<Efo>) -> Self {
+        let index = Storage::archive(&Index::build(&associations));
+        Self::with_storage(Storage::archive(&associations), index, efos)
+    }
+
+    fn with_storage(associations: Storage, index: Storage, efos: Vec<Efo>) -> Self {
+        let by_id = efos
+            .iter()
+            .enumerate()
+            .map(|(i, efo)| (efo.id, i))
+            .collect();
+        Self {
+            associations,
+            index,
+            efos,
+            by_id,
+            hgnc: None,
+        }
+    }
+
+    /// Resolves queried genes against `hgnc`, see [`resolve_gene`](Self::resolve_gene).
+    #[inline]
+    pub fn with_hgnc(mut self, hgnc: Hgnc) -> Self {
+        self.hgnc = Some(hgnc);
+        self
+    }
+
+    /// Loads the processed archives from the data directory, see
+    /// [`check_for_updates`](crate::files::check_for_updates) to create or
+    /// refresh them.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let associations = map_associations(dir)?;
+        // validated by `map_associations`
+        let index = map_index(dir, unsafe { archived_associations(&associations) })?;
+        let catalog = Self::with_storage(
+            Storage::Mapped(associations),
+            Storage::Mapped(index),
+            load_efo(dir)?,
+        );
+        Ok(match load_hgnc(dir)? {
+            Some(hgnc) => catalog.with_hgnc(hgnc),
+            None => catalog,
+        })
+    }
+
+    #[inline]
+    pub fn associations(&self) -> &[ArchivedAssociation] {
+        // validated by `map_associations` or serialized by `new`
+        unsafe { archived_associations(self.associations.bytes()) }
+    }
+
+    #[inline]
+    fn index(&self) -> &ArchivedIndex {
+        // validated by `map_index` or serialized by `new`
+        unsafe { archived_index(self.index.bytes()) }
+    }
+
+    /// The associations at the given offsets into [`associations`](Self::associations).
+    fn at(&self, offsets: Vec<usize>) -> impl Iterator<Item = &ArchivedAssociation> {
+        let associations = self.associations();
+        offsets.into_iter().map(move |i| &associations[i])
+    }
+
+    #[inline]
+    pub fn efos(&self) -> &[Efo] {
+        &self.efos
+    }
+
+    #[inline]
+    pub fn efo(&self, id: TermId) -> Option<&Efo> {
+        self.by_id.get(&id).map(|i| &self.efos[*i])
+    }
+
+    /// Label of the term with the given id, falling back to its CURIE for
+    /// terms missing from the EFO archive.
+    pub fn term_label(&self, id: TermId) -> String {
+        match self.efo(id) {
+            Some(efo) => efo.label.clone(),
+            None => id.to_string(),
+        }
+    }
+
+    /// Finds a term by its uppercase label, falling back to its synonyms.
+    pub fn find_efo(&self, label: &str) -> Option<&Efo> {
+        match self.efos.iter().find(|i| i.label == *label) {
+            None => self.efos.iter().find(|i| i.synonyms.contains(label)),
+            efo => efo,
+        }
+    }
+
+    /// Resolves an uppercase gene symbol or identifier to its approved HGNC
+    /// symbol, `None` if no HGNC complete set has been imported.
+    #[inline]
+    pub fn resolve_gene(&self, gene: &str) -> Option<GeneResolution> {
+        self.hgnc.as_ref().map(|i| i.resolve(gene))
+    }
+
+    /// Terms whose label or synonyms best match the uppercase `query`, see
+    /// [`query::search_efo`].
+    #[inline]
+    pub fn search_efo(&self, query: &str, limit: usize) -> Vec<EfoMatch<'_>> {
+        query::search_efo(&self.efos, query, limit)
+    }
+
+    /// Collects `root` and every term below it in the `children` graph, up to
+    /// `max_depth` levels down (unlimited if `None`).
+    pub fn descendants<'a>(
+        &'a self,
+        root: &'a Efo,
+        max_depth: Option<usize>,
+    ) -> HashMap<TermId, &'a Efo> {
+        let mut terms = HashMap::new();
+        let mut queue = VecDeque::from([(root, 0)]);
+        terms.insert(root.id, root);
+        while let Some((efo, depth)) = queue.pop_front() {
+            if max_depth.is_some_and(|max| depth >= max) {
+                continue;
+            }
+            for child in &efo.children {
+                if let Some(child) = self.efo(*child) {
+                    if terms.insert(child.id, child).is_none() {
+                        queue.push_back((child, depth + 1));
+                    }
+                }
+            }
+        }
+        terms
+    }
+
+    /// Associations annotated to any of `terms`, which should include `efo`
+    /// itself.
+    #[inline]
+    pub fn query_trait<'a>(
+        &'a self,
+        efo: &'a Efo,
+        terms: HashMap<TermId, &'a Efo>,
+        filter: &Filter,
+    ) -> TraitMatches<'a, ArchivedAssociation> {
+        let offsets = self.index().trait_offsets(terms.keys().copied());
+        query::query_trait(self.at(offsets), efo, terms, filter)
+    }
+
+    /// Associations mapped to the given uppercase gene symbol, or flanked by it
+    /// or reported for it if the filter allows.
+    #[inline]
+    pub fn query_gene(
+        &self,
+        gene: String,
+        filter: &Filter,
+    ) -> GeneMatches<'_, ArchivedAssociation> {
+        let mut offsets = filter
+            .symbols(&gene)
+            .flat_map(|i| self.index().gene_offsets(i))
+            .collect::<Vec<_>>();
+        offsets.sort_unstable();
+        offsets.dedup();
+        query::query_gene(self.at(offsets), gene, filter)
+    }
+
+    /// Associations reported for the given lowercase variant.
+    #[inline]
+    pub fn query_variant(
+        &self,
+        variant: String,
+        filter: &Filter,
+    ) -> VariantMatches<'_, ArchivedAssociation> {
+        let offsets = self.index().variant_offsets(&variant);
+        query::query_variant(self.at(offsets), variant, filter)
+    }
+
+    /// Associations located in `region`, optionally restricted to `terms`.
+    #[inline]
+    pub fn query_region(
+        &self,
+        region: GenomicRegion,
+        terms: Option<&HashMap<TermId, &Efo>>,
+        filter: &Filter,
+    ) -> RegionMatches<'_, ArchivedAssociation> {
+        match terms {
+            Some(terms) => {
+                let offsets = self.index().trait_offsets(terms.keys().copied());
+                query::query_region(self.at(offsets), region, Some(terms), filter)
+            },
+            None => query::query_region(self.associations(), region, None, filter),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    fn efo(id: &str, children: &[&str]) -> Efo {
+        Efo {
+            id: TermId::parse(id).unwrap(),
+            label: id.to_string(),
+            parents: Vec::new(),
+            relations: Vec::new(),
+            children: children.iter().map(|i| TermId::parse(i).unwrap()).collect(),
+            synonyms: HashSet::new(),
+        }
+    }
+
+    fn descendants(catalog: &Catalog, max_depth: Option<usize>) -> Vec<String> {
+        let root = catalog.efo(TermId::parse("EFO_0000001").unwrap()).unwrap();
+        let mut ids = catalog
+            .descendants(root, max_depth)
+            .into_keys()
+            .map(|i| i.to_string())
+            .collect::<Vec<_>>();
+        ids.sort();
+        ids
+    }
+
+    #[test]
+    fn descendants_max_depth() {
+        // 1 -> 2 -> 3 -> 4, and 1 -> 3 directly
+        let catalog = Catalog::new(
+            Vec::new(),
+            vec![
+                efo("EFO_0000001", &["EFO_0000002", "EFO_0000003"]),
+                efo("EFO_0000002", &["EFO_0000003"]),
+                efo("EFO_0000003", &["EFO_0000004"]),
+                efo("EFO_0000004", &[]),
+            ],
+        );
+        assert_eq!(descendants(&catalog, Some(0)), ["EFO_0000001"]);
+        assert_eq!(
+            descendants(&catalog, Some(1)),
+            ["EFO_0000001", "EFO_0000002", "EFO_0000003"]
+        );
+        // 3 is a child as well as a grandchild, so 4 is only 2 levels down
+        assert_eq!(
+            descendants(&catalog, Some(2)),
+            ["EFO_0000001", "EFO_0000002", "EFO_0000003", "EFO_0000004"]
+        );
+        assert_eq!(descendants(&catalog, None), descendants(&catalog, Some(2)));
+    }
+
+    #[test]
+    fn descendants_skips_missing_terms() {
+        let catalog = Catalog::new(Vec::new(), vec![efo("EFO_0000001", &["EFO_0000002"])]);
+        assert_eq!(descendants(&catalog, None), ["EFO_0000001"]);
+    }
+}
@@ -1,13 +1,33 @@
-use clap::{Args, Parser, Subcommand};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use search_gwas::{
+    config::check_threshold,
+    data::{AzDataset, Direction, Efo, GeneResolution, GeneSource, GenomicRegion, TermId},
+    error::{Error, Result},
+    files::{
+        check_archives, check_for_updates, get_az_dir, import, load_az_associations, reprocess,
+        update_az, AzSource, ImportSource,
+    },
+    query::{parse_genes, parse_variants, query_az, Filter},
+    Catalog,
+};
 
 use crate::{
-    files::{check_for_updates, get_az_dir, load_associations, load_efo},
-    query::{find_efo, parse_genes, query, query_az},
+    interactive,
+    output::{
+        print_az, print_batch, print_efo_search, print_gene, print_region, print_trait,
+        print_variant, BatchTrait, Display, OutputFormat,
+    },
     Context,
 };
 
 pub trait Run {
-    fn run(self, ctx: Context);
+    fn run(self, ctx: Context) -> Result<()>;
 }
 
 #[derive(Parser)]
@@ -15,21 +35,142 @@ pub trait Run {
 pub struct Cli {
     #[command(subcommand)]
     command: Commands,
+    #[arg(
+        long = "p-threshold",
+        global = true,
+        value_parser = parse_threshold,
+        help = "Only consider associations with a p-value below this threshold [default: config file or 1e-5]"
+    )]
+    p_threshold: Option<f64>,
+    #[arg(
+        long,
+        global = true,
+        env = "SEARCH_GWAS_OFFLINE",
+        value_parser = clap::builder::BoolishValueParser::new(),
+        help = "Never touch the network, only use the data already in the data directory"
+    )]
+    offline: bool,
 }
 
 impl Run for Cli {
     #[inline]
-    fn run(self, ctx: Context) {
-        self.command.run(ctx);
+    fn run(self, mut ctx: Context) -> Result<()> {
+        if let Some(p_threshold) = self.p_threshold {
+            ctx.config.p_threshold = p_threshold;
+        }
+        ctx.offline = self.offline;
+        self.command.run(ctx)
+    }
+}
+
+/// Makes sure the processed data exists, checking for updates unless offline.
+/// If the check can't reach the network, existing data is used anyway.
+fn prepare(ctx: &Context) -> Result<()> {
+    if ctx.offline {
+        return check_archives(&ctx.dir);
+    }
+    match check_for_updates(&ctx.dir, false, 0) {
+        Err(e @ Error::Network { .. }) if check_archives(&ctx.dir).is_ok() => {
+            log::warn!("{e}\nUsing the existing data");
+            Ok(())
+        },
+        result => result,
+    }
+}
+
+fn parse_threshold(s: &str) -> Result<f64, String> {
+    check_threshold(s.parse::<f64>().map_err(|e| e.to_string())?)
+}
+
+/// Finds the term with the CURIE or URI `label`, or otherwise labelled `label`,
+/// suggesting the closest terms if there is none.
+fn find_efo<'a>(catalog: &'a Catalog, label: &str) -> Result<&'a Efo> {
+    let orig = label.trim();
+    if let Some(id) = TermId::parse(orig) {
+        return match catalog.efo(id) {
+            Some(efo) => {
+                eprintln!("Resolved {orig} to {}", efo.label);
+                Ok(efo)
+            },
+            None => Err(Error::UnknownTrait {
+                term: id.to_string(),
+                suggestions: Vec::new(),
+            }),
+        };
     }
+    let label = orig.to_uppercase();
+    catalog.find_efo(&label).ok_or_else(|| Error::UnknownTrait {
+        term: orig.to_string(),
+        suggestions: catalog
+            .search_efo(&label, 3)
+            .into_iter()
+            .map(|i| format!("{} ({})", i.efo.label, i.efo.id))
+            .collect(),
+    })
+}
+
+/// Resolves `genes` to their approved HGNC symbols if an HGNC complete set has
+/// been imported, reporting what each resolved to. Ambiguous and unknown genes
+/// are queried as given, and genes resolving to the same symbol only once.
+/// The symbols genes were given as are recorded in `filter`, so associations
+/// still listing them match too.
+pub(crate) fn resolve_genes(
+    catalog: &Catalog,
+    genes: Vec<String>,
+    filter: &mut Filter,
+) -> Vec<String> {
+    let mut seen = HashSet::new();
+    filter.aliases.clear();
+    genes
+        .into_iter()
+        .map(|gene| match catalog.resolve_gene(&gene) {
+            Some(GeneResolution::Resolved { symbol, kind }) => {
+                eprintln!("Resolved {gene} to {symbol} ({kind})");
+                let aliases = filter.aliases.entry(symbol.clone()).or_default();
+                if !aliases.contains(&gene) {
+                    aliases.push(gene);
+                }
+                symbol
+            },
+            Some(GeneResolution::Ambiguous { symbols, kind }) => {
+                eprintln!(
+                    "{gene} is an ambiguous {kind} of {}, querying it as given",
+                    symbols.join(", ")
+                );
+                gene
+            },
+            Some(GeneResolution::Unknown) => {
+                eprintln!("{gene} is not an HGNC symbol or identifier, querying it as given");
+                gene
+            },
+            Some(GeneResolution::Approved) | None => gene,
+        })
+        .filter(|i| seen.insert(i.clone()))
+        .collect()
 }
 
 #[derive(Subcommand)]
 enum Commands {
     #[command(about = "Download the latest GWAS and EFO data if available")]
     Update(Update),
+    #[command(
+        about = "Import and pin a GWAS Catalog and/or EFO release, or import an HGNC complete set, from a file or URL"
+    )]
+    Import(Import),
+    #[command(about = "Search EFO labels and synonyms for terms to query")]
+    SearchEfo(SearchEfo),
     #[command(about = "Query the GWAS catalog for a trait")]
     Trait(Trait),
+    #[command(about = "Query the GWAS catalog for the traits associated with a gene")]
+    Gene(Gene),
+    #[command(about = "Query the GWAS catalog for the traits associated with a variant")]
+    Variant(Variant),
+    #[command(about = "Query the GWAS catalog for associations in a genomic region")]
+    Region(Region),
+    #[command(about = "Load the catalog once and query traits and genes interactively")]
+    Interactive(Interactive),
+    #[command(about = "Query the GWAS catalog for many traits against a list of genes at once")]
+    Batch(Batch),
     #[command(about = "Update the AstraZeneca PheWAS catalog", hide = true)]
     AzUpdate(AzUpdate),
     #[command(about = "Query the AstraZeneca PheWAS catalog for a trait")]
@@ -38,10 +179,17 @@ enum Commands {
 
 impl Run for Commands {
     #[inline]
-    fn run(self, ctx: Context) {
+    fn run(self, ctx: Context) -> Result<()> {
         match self {
             Self::Update(update) => update.run(ctx),
+            Self::Import(import) => import.run(ctx),
+            Self::SearchEfo(search) => search.run(ctx),
             Self::Trait(query) => query.run(ctx),
+            Self::Gene(query) => query.run(ctx),
+            Self::Variant(query) => query.run(ctx),
+            Self::Region(query) => query.run(ctx),
+            Self::Interactive(session) => session.run(ctx),
+            Self::Batch(query) => query.run(ctx),
             Self::AzUpdate(update) => update.run(ctx),
             Self::AzTrait(query) => query.run(ctx),
         }
@@ -63,22 +211,306 @@ struct Update {
 }
 
 impl Run for Update {
-    fn run(self, ctx: Context) {
-        check_for_updates(
-            &ctx.dir,
-            self.reprocess,
-            if self.reprocess { 2 } else { self.force },
-        );
+    fn run(self, ctx: Context) -> Result<()> {
+        if ctx.offline {
+            if !self.reprocess {
+                eprintln!("Updates are disabled in offline mode, pass --reprocess to reprocess the local files");
+                return Ok(());
+            }
+            reprocess(&ctx.dir)?;
+        } else {
+            check_for_updates(
+                &ctx.dir,
+                self.reprocess,
+                if self.reprocess { 2 } else { self.force },
+            )?;
+        }
         println!("Up to date!");
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+#[command(group(clap::ArgGroup::new("source").required(true).multiple(true).args(["gwas", "efo", "hgnc"])))]
+struct Import {
+    #[arg(
+        long,
+        help = "Path or URL of a GWAS Catalog associations TSV (v1.0.2 with ontology annotations), optionally gzipped or zipped"
+    )]
+    gwas: Option<String>,
+    #[arg(
+        long,
+        help = "Path or URL of an EFO OWL file, optionally gzipped or zipped"
+    )]
+    efo: Option<String>,
+    #[arg(
+        long,
+        help = "Path or URL of an HGNC complete set TSV (hgnc_complete_set.txt) to resolve gene aliases, previous symbols and IDs with, optionally gzipped or zipped"
+    )]
+    hgnc: Option<String>,
+}
+
+impl Run for Import {
+    fn run(self, ctx: Context) -> Result<()> {
+        let gwas = self.gwas.as_deref().map(ImportSource::new);
+        let efo = self.efo.as_deref().map(ImportSource::new);
+        let hgnc = self.hgnc.as_deref().map(ImportSource::new);
+        if ctx.offline {
+            if let Some(source) = gwas.iter().chain(&efo).chain(&hgnc).find(|i| i.is_url()) {
+                return Err(Error::DownloadDisabled { url: source.name() });
+            }
+        }
+        let pinned = gwas.is_some() || efo.is_some();
+        import(&ctx.dir, gwas, efo, hgnc)?;
+        if pinned {
+            println!("Imported and pinned, run `search-gwas update --force` to switch back to the latest releases");
+        } else {
+            println!("Imported");
+        }
+        Ok(())
+    }
+}
+
+/// [`Direction`] as a command line value.
+#[derive(Clone, Copy, ValueEnum)]
+enum DirectionArg {
+    Risk,
+    Protective,
+}
+
+impl From<DirectionArg> for Direction {
+    fn from(direction: DirectionArg) -> Self {
+        match direction {
+            DirectionArg::Risk => Self::Risk,
+            DirectionArg::Protective => Self::Protective,
+        }
+    }
+}
+
+/// [`GeneSource`] as a command line value.
+#[derive(Clone, Copy, Default, ValueEnum)]
+enum GeneSourceArg {
+    /// Genes mapped by the catalog from the variant's position
+    #[default]
+    Mapped,
+    /// Genes named by the study's authors
+    Reported,
+    /// Either
+    Both,
+}
+
+impl From<GeneSourceArg> for GeneSource {
+    fn from(source: GeneSourceArg) -> Self {
+        match source {
+            GeneSourceArg::Mapped => Self::Mapped,
+            GeneSourceArg::Reported => Self::Reported,
+            GeneSourceArg::Both => Self::Both,
+        }
+    }
+}
+
+/// [`AzDataset`] as a command line value.
+#[derive(Clone, Copy, ValueEnum)]
+enum AzDatasetArg {
+    Binary,
+    Proteomics,
+    Quantitative,
+}
+
+impl From<AzDatasetArg> for AzDataset {
+    fn from(dataset: AzDatasetArg) -> Self {
+        match dataset {
+            AzDatasetArg::Binary => Self::Binary,
+            AzDatasetArg::Proteomics => Self::Proteomics,
+            AzDatasetArg::Quantitative => Self::Quantitative,
+        }
+    }
+}
+
+#[derive(Args)]
+struct EffectArgs {
+    #[arg(
+        long = "min-effect",
+        help = "Only include associations with at least this effect size (odds ratios below 1 are inverted, betas are absolute, effects with neither a unit nor a CI are excluded)"
+    )]
+    min_effect: Option<f64>,
+    #[arg(long, help = "Only include associations with this direction of effect")]
+    direction: Option<DirectionArg>,
+}
+
+impl EffectArgs {
+    fn filter(&self, threshold: f64) -> Filter {
+        Filter {
+            threshold,
+            min_effect: self.min_effect,
+            direction: self.direction.map(Direction::from),
+            flanking: true,
+            gene_source: GeneSource::Mapped,
+            aliases: HashMap::new(),
+        }
+    }
+}
+
+#[derive(Args)]
+struct GeneMatchArgs {
+    #[arg(
+        long = "gene-source",
+        value_enum,
+        default_value_t,
+        help = "Which genes of an association to match queried genes against"
+    )]
+    gene_source: GeneSourceArg,
+    #[arg(
+        long = "exclude-flanking",
+        help = "Only match genes a variant lies in, not the genes either side of an intergenic variant"
+    )]
+    exclude_flanking: bool,
+}
+
+impl GeneMatchArgs {
+    #[inline]
+    fn filter(&self, filter: Filter) -> Filter {
+        Filter {
+            flanking: !self.exclude_flanking,
+            gene_source: self.gene_source.into(),
+            ..filter
+        }
+    }
+}
+
+#[derive(Args)]
+struct GeneFileArgs {
+    #[arg(
+        long = "gene-file",
+        help = "Read genes from a file, or standard input if -, one per line with # comments"
+    )]
+    gene_file: Option<PathBuf>,
+    #[arg(
+        long = "gene-column",
+        requires = "gene_file",
+        help = "Read genes from this column of a CSV or TSV gene file with a header instead"
+    )]
+    gene_column: Option<String>,
+}
+
+impl GeneFileArgs {
+    /// The uppercase genes given on the command line, where `-` reads them from
+    /// standard input, followed by those in the gene file.
+    fn genes(&self, genes: &[String]) -> Result<Vec<String>> {
+        let mut all = Vec::with_capacity(genes.len());
+        for gene in genes {
+            if gene == "-" {
+                all.extend(list_lines(&read_input(Path::new("-"))?));
+            } else {
+                all.push(gene.clone());
+            }
+        }
+        if let Some(path) = &self.gene_file {
+            let file = read_input(path)?;
+            match &self.gene_column {
+                Some(column) => all.extend(gene_column(path, &file, column)?),
+                None => all.extend(list_lines(&file)),
+            }
+        }
+        Ok(parse_genes(&all))
+    }
+}
+
+/// Reads the file at `path`, or standard input if it is `-`.
+fn read_input(path: &Path) -> Result<String> {
+    if path == Path::new("-") {
+        std::io::read_to_string(std::io::stdin()).map_err(Error::io("standard input"))
+    } else {
+        std::fs::read_to_string(path).map_err(Error::io(path.display().to_string()))
+    }
+}
+
+/// Entries listed one per line, ignoring blank lines and anything after a `#`.
+fn list_lines(file: &str) -> impl Iterator<Item = String> + '_ {
+    file.lines()
+        .filter_map(|i| i.split('#').next())
+        .map(str::trim)
+        .filter(|i| !i.is_empty())
+        .map(String::from)
+}
+
+/// Genes in the named column of a CSV or TSV file with a header, tab
+/// separated if the header contains a tab. Lines starting with `#` are
+/// skipped.
+fn gene_column(path: &Path, file: &str, column: &str) -> Result<Vec<String>> {
+    let name = path.display().to_string();
+    let header = file.lines().find(|i| !i.starts_with('#'));
+    let delimiter = if header.is_some_and(|i| i.contains('\t')) {
+        b'\t'
+    } else {
+        b','
+    };
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .flexible(true)
+        .comment(Some(b'#'))
+        .from_reader(file.as_bytes());
+    let position = reader
+        .headers()
+        .map_err(|e| Error::parse(&name, format!("failed to read the header: {e}")))?
+        .iter()
+        .position(|i| i.trim().eq_ignore_ascii_case(column))
+        .ok_or_else(|| Error::parse(&name, format!("missing the \"{column}\" column")))?;
+    let mut genes = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| Error::parse(&name, format!("invalid record: {e}")))?;
+        match record.get(position).map(str::trim) {
+            Some(gene) if !gene.is_empty() => genes.push(gene.to_string()),
+            _ => {},
+        }
+    }
+    Ok(genes)
+}
+
+#[derive(Args)]
+struct SearchEfo {
+    #[arg(help = "Full or partial EFO label or synonym, typos allowed")]
+    query: String,
+    #[arg(
+        short = 'n',
+        long,
+        default_value_t = 10,
+        help = "Maximum number of terms to list"
+    )]
+    limit: usize,
+    #[arg(short, long, help = "Replace tables with CSV output")]
+    csv: bool,
+}
+
+impl Run for SearchEfo {
+    fn run(self, ctx: Context) -> Result<()> {
+        prepare(&ctx)?;
+        let catalog = Catalog::load(&ctx.dir)?;
+        let query = self.query.trim();
+        print_efo_search(
+            query,
+            catalog.search_efo(&query.to_uppercase(), self.limit),
+            self.csv,
+        );
+        Ok(())
     }
 }
 
 #[derive(Args)]
 struct Trait {
-    #[arg(help = "The EFO label to query")]
+    #[arg(help = "The EFO label, CURIE (e.g. EFO_0004705 or MONDO:0005068) or term URI to query")]
     efo: String,
-    #[arg(short, long, action = clap::ArgAction::Append, help = "Gene(s) to query")]
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Append,
+        help = "Gene(s) to query, - to read them from standard input"
+    )]
     gene: Vec<String>,
+    #[command(flatten)]
+    gene_file: GeneFileArgs,
+    #[command(flatten)]
+    gene_match: GeneMatchArgs,
     #[arg(
         short = 'a',
         long = "with-associations",
@@ -91,41 +523,431 @@ struct Trait {
         help = "Show PubMed links instead of IDs"
     )]
     with_pubmed_links: bool,
-    #[arg(short, long, help = "Replace tables with CSV output")]
+    #[arg(
+        short,
+        long,
+        conflicts_with = "format",
+        help = "Replace tables with CSV output, same as --format csv"
+    )]
     csv: bool,
+    #[arg(long, value_enum, default_value_t, help = "Output format")]
+    format: OutputFormat,
+    #[command(flatten)]
+    effect: EffectArgs,
+    #[arg(
+        short = 'd',
+        long = "include-descendants",
+        help = "Also match associations annotated to any descendant of the EFO term"
+    )]
+    include_descendants: bool,
+    #[arg(
+        long = "max-depth",
+        requires = "include_descendants",
+        help = "Only include descendants up to this many levels below the EFO term"
+    )]
+    max_depth: Option<usize>,
 }
 
 impl Run for Trait {
-    fn run(self, ctx: Context) {
-        check_for_updates(&ctx.dir, false, 0);
-        let orig = self.efo.trim();
-        let genes = parse_genes(&self.gene);
-        let efos = load_efo(&ctx.dir);
-        let associations = load_associations(&ctx.dir);
-        let efo = match find_efo(&efos, &orig.to_uppercase()) {
-            Some(efo) => efo,
-            None => {
-                eprintln!("\"{orig}\" is not a valid EFO label");
-                return;
-            },
+    fn run(self, ctx: Context) -> Result<()> {
+        let genes = self.gene_file.genes(&self.gene)?;
+        prepare(&ctx)?;
+        let catalog = Catalog::load(&ctx.dir)?;
+        let mut filter = self
+            .gene_match
+            .filter(self.effect.filter(ctx.config.p_threshold));
+        let genes = resolve_genes(&catalog, genes, &mut filter);
+        let efo = find_efo(&catalog, &self.efo)?;
+        let terms = if self.include_descendants {
+            catalog.descendants(efo, self.max_depth)
+        } else {
+            HashMap::from([(efo.id, efo)])
         };
-        query(
-            efo,
+        print_trait(
+            catalog.query_trait(efo, terms, &filter),
             genes,
-            &associations,
-            self.with_associations,
-            self.with_pubmed_links,
-            self.csv,
+            Display {
+                with_associations: self.with_associations,
+                with_pubmed_links: self.with_pubmed_links,
+                format: if self.csv {
+                    OutputFormat::Csv
+                } else {
+                    self.format
+                },
+            },
+            filter.threshold,
         );
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+struct Gene {
+    #[arg(
+        required_unless_present = "gene_file",
+        help = "The gene(s) to query, - to read them from standard input"
+    )]
+    genes: Vec<String>,
+    #[command(flatten)]
+    gene_file: GeneFileArgs,
+    #[command(flatten)]
+    gene_match: GeneMatchArgs,
+    #[arg(
+        short = 'a',
+        long = "with-associations",
+        help = "Show full association data"
+    )]
+    with_associations: bool,
+    #[arg(
+        short = 'l',
+        long = "with-pubmed-links",
+        help = "Show PubMed links instead of IDs"
+    )]
+    with_pubmed_links: bool,
+    #[arg(short, long, help = "Replace tables with CSV output")]
+    csv: bool,
+    #[command(flatten)]
+    effect: EffectArgs,
+}
+
+impl Run for Gene {
+    fn run(self, ctx: Context) -> Result<()> {
+        let genes = self.gene_file.genes(&self.genes)?;
+        prepare(&ctx)?;
+        let catalog = Catalog::load(&ctx.dir)?;
+        let mut filter = self
+            .gene_match
+            .filter(self.effect.filter(ctx.config.p_threshold));
+        let genes = resolve_genes(&catalog, genes, &mut filter);
+        let display = Display {
+            with_associations: self.with_associations,
+            with_pubmed_links: self.with_pubmed_links,
+            format: if self.csv {
+                OutputFormat::Csv
+            } else {
+                OutputFormat::Table
+            },
+        };
+        for gene in genes {
+            print_gene(
+                &catalog,
+                catalog.query_gene(gene, &filter),
+                display,
+                filter.threshold,
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+struct Variant {
+    #[arg(required_unless_present = "file", help = "The rsID(s) to query")]
+    variants: Vec<String>,
+    #[arg(short, long, help = "Read rsIDs from a file, one per line")]
+    file: Option<PathBuf>,
+    #[arg(
+        short = 'l',
+        long = "with-pubmed-links",
+        help = "Show PubMed links instead of IDs"
+    )]
+    with_pubmed_links: bool,
+    #[arg(short, long, help = "Replace tables with CSV output")]
+    csv: bool,
+}
+
+impl Run for Variant {
+    fn run(self, ctx: Context) -> Result<()> {
+        let mut variants = self.variants;
+        if let Some(file) = &self.file {
+            let file =
+                std::fs::read_to_string(file).map_err(Error::io(file.display().to_string()))?;
+            variants.extend(
+                file.lines()
+                    .map(str::trim)
+                    .filter(|i| !i.is_empty() && !i.starts_with('#'))
+                    .map(String::from),
+            );
+        }
+        prepare(&ctx)?;
+        let variants = parse_variants(&variants);
+        let catalog = Catalog::load(&ctx.dir)?;
+        let filter = Filter::new(ctx.config.p_threshold);
+        let display = Display {
+            with_associations: true,
+            with_pubmed_links: self.with_pubmed_links,
+            format: if self.csv {
+                OutputFormat::Csv
+            } else {
+                OutputFormat::Table
+            },
+        };
+        for variant in variants {
+            print_variant(
+                &catalog,
+                catalog.query_variant(variant, &filter),
+                display,
+                filter.threshold,
+            );
+        }
+        Ok(())
     }
 }
 
 #[derive(Args)]
-struct AzUpdate;
+struct Region {
+    #[arg(
+        required_unless_present = "bed",
+        help = "The region(s) to query, as chr:start-end (1-based, inclusive, GRCh38)"
+    )]
+    regions: Vec<String>,
+    #[arg(short, long, help = "Read regions from a BED file")]
+    bed: Option<PathBuf>,
+    #[arg(
+        short = 't',
+        long = "trait",
+        help = "Only include associations with this EFO label, CURIE or term URI"
+    )]
+    efo: Option<String>,
+    #[arg(
+        short = 'd',
+        long = "include-descendants",
+        requires = "efo",
+        help = "Also match associations annotated to any descendant of the EFO term"
+    )]
+    include_descendants: bool,
+    #[arg(
+        short = 'l',
+        long = "with-pubmed-links",
+        help = "Show PubMed links instead of IDs"
+    )]
+    with_pubmed_links: bool,
+    #[arg(short, long, help = "Replace tables with CSV output")]
+    csv: bool,
+}
+
+impl Run for Region {
+    fn run(self, ctx: Context) -> Result<()> {
+        let mut regions = Vec::with_capacity(self.regions.len());
+        for region in &self.regions {
+            match GenomicRegion::parse(region) {
+                Some(region) => regions.push(region),
+                None => {
+                    return Err(Error::parse(
+                        format!("\"{region}\""),
+                        "expected a region as chr:start-end",
+                    ))
+                },
+            }
+        }
+        if let Some(bed) = &self.bed {
+            let file =
+                std::fs::read_to_string(bed).map_err(Error::io(bed.display().to_string()))?;
+            for (i, line) in file.lines().enumerate() {
+                if line.trim().is_empty()
+                    || line.starts_with('#')
+                    || line.starts_with("track")
+                    || line.starts_with("browser")
+                {
+                    continue;
+                }
+                match GenomicRegion::parse_bed(line) {
+                    Some(region) => regions.push(region),
+                    None => {
+                        return Err(Error::parse(
+                            bed.display().to_string(),
+                            format!("invalid BED record on line {}", i + 1),
+                        ))
+                    },
+                }
+            }
+        }
+        prepare(&ctx)?;
+        let catalog = Catalog::load(&ctx.dir)?;
+        let terms = match &self.efo {
+            Some(label) => {
+                let efo = find_efo(&catalog, label)?;
+                Some(if self.include_descendants {
+                    catalog.descendants(efo, None)
+                } else {
+                    HashMap::from([(efo.id, efo)])
+                })
+            },
+            None => None,
+        };
+        let filter = Filter::new(ctx.config.p_threshold);
+        let display = Display {
+            with_associations: true,
+            with_pubmed_links: self.with_pubmed_links,
+            format: if self.csv {
+                OutputFormat::Csv
+            } else {
+                OutputFormat::Table
+            },
+        };
+        for region in regions {
+            print_region(
+                &catalog,
+                catalog.query_region(region, terms.as_ref(), &filter),
+                display,
+                filter.threshold,
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+struct Interactive {
+    #[arg(
+        short = 'a',
+        long = "with-associations",
+        help = "Start with full association data shown"
+    )]
+    with_associations: bool,
+    #[command(flatten)]
+    gene_match: GeneMatchArgs,
+    #[arg(
+        short = 'l',
+        long = "with-pubmed-links",
+        help = "Show PubMed links instead of IDs"
+    )]
+    with_pubmed_links: bool,
+}
+
+impl Run for Interactive {
+    fn run(self, ctx: Context) -> Result<()> {
+        prepare(&ctx)?;
+        let catalog = Catalog::load(&ctx.dir)?;
+        let display = Display {
+            with_associations: self.with_associations,
+            with_pubmed_links: self.with_pubmed_links,
+            format: OutputFormat::Table,
+        };
+        let filter = self.gene_match.filter(Filter::new(ctx.config.p_threshold));
+        interactive::run(&ctx, &catalog, filter, display)
+    }
+}
+
+#[derive(Args)]
+struct Batch {
+    #[arg(
+        short = 't',
+        long = "trait-file",
+        help = "Read the EFO labels, CURIEs or term URIs to query from a file, or standard input if -, one per line with # comments"
+    )]
+    trait_file: PathBuf,
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Append,
+        required_unless_present = "gene_file",
+        help = "Gene(s) to query, - to read them from standard input"
+    )]
+    gene: Vec<String>,
+    #[command(flatten)]
+    gene_file: GeneFileArgs,
+    #[command(flatten)]
+    gene_match: GeneMatchArgs,
+    #[arg(
+        short,
+        long,
+        help = "Write a trait by gene matrix of the best p-values instead of one row per trait and associated gene"
+    )]
+    matrix: bool,
+    #[arg(long, value_enum, default_value_t, help = "Output format")]
+    format: OutputFormat,
+    #[command(flatten)]
+    effect: EffectArgs,
+    #[arg(
+        short = 'd',
+        long = "include-descendants",
+        help = "Also match associations annotated to any descendant of each EFO term"
+    )]
+    include_descendants: bool,
+    #[arg(
+        long = "max-depth",
+        requires = "include_descendants",
+        help = "Only include descendants up to this many levels below each EFO term"
+    )]
+    max_depth: Option<usize>,
+}
+
+impl Run for Batch {
+    fn run(self, ctx: Context) -> Result<()> {
+        if self.matrix && matches!(self.format, OutputFormat::Json | OutputFormat::Ndjson) {
+            return Err(Error::Usage(
+                "--matrix can only be written as a table, CSV or TSV".to_string(),
+            ));
+        }
+        let traits = list_lines(&read_input(&self.trait_file)?).collect::<Vec<_>>();
+        let genes = self.gene_file.genes(&self.gene)?;
+        prepare(&ctx)?;
+        let catalog = Catalog::load(&ctx.dir)?;
+        let mut filter = self
+            .gene_match
+            .filter(self.effect.filter(ctx.config.p_threshold));
+        let genes = resolve_genes(&catalog, genes, &mut filter);
+        let mut results = Vec::with_capacity(traits.len());
+        for label in &traits {
+            // unknown traits are reported and skipped so one typo doesn't
+            // lose the rest of the run
+            let efo = match find_efo(&catalog, label) {
+                Ok(efo) => efo,
+                Err(e) => {
+                    eprintln!("{e}");
+                    continue;
+                },
+            };
+            let terms = if self.include_descendants {
+                catalog.descendants(efo, self.max_depth)
+            } else {
+                HashMap::from([(efo.id, efo)])
+            };
+            results.push(BatchTrait {
+                label: &efo.label,
+                summaries: catalog.query_trait(efo, terms, &filter).summarize(&genes),
+            });
+        }
+        print_batch(&genes, &results, self.format, self.matrix);
+        Ok(())
+    }
+}
+
+#[derive(Args)]
+struct AzUpdate {
+    #[arg(
+        long,
+        conflicts_with = "path",
+        help = "Base URL to download binary.csv.gz, proteomics.csv.gz and quantitative.csv.gz from [default: az_url in the config file]"
+    )]
+    url: Option<String>,
+    #[arg(
+        long,
+        help = "Directory containing binary.csv.gz, proteomics.csv.gz and/or quantitative.csv.gz"
+    )]
+    path: Option<PathBuf>,
+}
 
 impl Run for AzUpdate {
-    fn run(self, ctx: Context) {
+    fn run(self, ctx: Context) -> Result<()> {
         let dir = get_az_dir();
+        let source = match (&self.path, self.url.as_ref().or(ctx.config.az_url.as_ref())) {
+            (Some(path), _) => AzSource::Path(path),
+            (None, Some(url)) if ctx.offline => {
+                return Err(Error::DownloadDisabled { url: url.clone() });
+            },
+            (None, Some(url)) => AzSource::Url(url),
+            (None, None) => {
+                return Err(Error::Usage(
+                    "no AZ PheWAS source given, pass --url or --path or set az_url in the config file"
+                        .to_string(),
+                ));
+            },
+        };
+        update_az(&dir, source)?;
+        println!("Up to date!");
+        Ok(())
     }
 }
 
@@ -133,22 +955,103 @@ impl Run for AzUpdate {
 struct AzTrait {
     #[arg(help = "The trait to query")]
     trait_: String,
-    #[arg(short, long, action = clap::ArgAction::Append, help = "Gene(s) to query")]
+    #[arg(
+        short,
+        long,
+        action = clap::ArgAction::Append,
+        help = "Gene(s) to query, - to read them from standard input"
+    )]
     gene: Vec<String>,
+    #[command(flatten)]
+    gene_file: GeneFileArgs,
     #[arg(
         short = 'a',
         long = "with-associations",
         help = "Show full association data"
     )]
     with_associations: bool,
-    #[arg(short, long, help = "Replace tables with CSV output")]
+    #[arg(
+        short,
+        long,
+        conflicts_with = "format",
+        help = "Replace tables with CSV output, same as --format csv"
+    )]
     csv: bool,
+    #[arg(long, value_enum, default_value_t, help = "Output format")]
+    format: OutputFormat,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Only search these datasets [default: all]"
+    )]
+    dataset: Vec<AzDatasetArg>,
 }
 
 impl Run for AzTrait {
-    fn run(self, _ctx: Context) {
+    fn run(self, ctx: Context) -> Result<()> {
         let orig = self.trait_.trim().to_lowercase();
-        let genes = parse_genes(&self.gene);
-        query_az(&orig, genes, self.with_associations, self.csv);
+        let genes = self.gene_file.genes(&self.gene)?;
+        let associations = match load_az_associations(&get_az_dir())? {
+            Some(associations) => associations,
+            None => {
+                eprintln!(
+                    "The AZ PheWAS data has not been indexed yet, run `search-gwas az-update`"
+                );
+                return Ok(());
+            },
+        };
+        let threshold = ctx.config.p_threshold;
+        let datasets = self
+            .dataset
+            .into_iter()
+            .map(AzDataset::from)
+            .collect::<Vec<_>>();
+        print_az(
+            &orig,
+            query_az(&associations, &orig, &datasets, threshold),
+            genes,
+            Display {
+                with_associations: self.with_associations,
+                with_pubmed_links: false,
+                format: if self.csv {
+                    OutputFormat::Csv
+                } else {
+                    self.format
+                },
+            },
+            threshold,
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn list_lines_skips_comments() {
+        let file = "# genes\nTSHR\n\n  PRKN  # parkin\n#PARK2\n";
+        assert_eq!(list_lines(file).collect::<Vec<_>>(), ["TSHR", "PRKN"]);
+    }
+
+    #[test]
+    fn gene_column_csv() {
+        let file = "variant,Gene\nrs1,TSHR\nrs2,\n# rs3,C\nrs4, PRKN \n";
+        let genes = gene_column(Path::new("genes.csv"), file, "gene").unwrap();
+        assert_eq!(genes, ["TSHR", "PRKN"]);
+    }
+
+    #[test]
+    fn gene_column_tsv() {
+        let file = "# exported\ngene\tp\nTSHR\t0.1\n# c\nPRKN\t0.2\n";
+        let genes = gene_column(Path::new("genes.tsv"), file, "gene").unwrap();
+        assert_eq!(genes, ["TSHR", "PRKN"]);
+    }
+
+    #[test]
+    fn gene_column_missing() {
+        let result = gene_column(Path::new("genes.csv"), "variant,p\nrs1,0.1\n", "gene");
+        assert!(matches!(result, Err(Error::Parse { .. })));
     }
 }
@@ -0,0 +1,58 @@
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::{
+    consts::DEFAULT_THRESHOLD,
+    error::{Error, Result},
+    files::config_path,
+};
+
+/// User defaults read from `config.toml` in the data directory, overridden by
+/// the equivalent command line options.
+#[derive(Debug, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub p_threshold: f64,
+    // base URL for `az-update` to download the AZ PheWAS CSVs from
+    pub az_url: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            p_threshold: DEFAULT_THRESHOLD,
+            az_url: None,
+        }
+    }
+}
+
+/// Checks that a p-value threshold is greater than 0 and at most 1, shared by
+/// the config file and `--p-threshold`.
+pub fn check_threshold(threshold: f64) -> Result<f64, String> {
+    if threshold > 0.0 && threshold <= 1.0 {
+        Ok(threshold)
+    } else {
+        Err("must be greater than 0 and at most 1".into())
+    }
+}
+
+impl Config {
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = config_path(dir);
+        match std::fs::read_to_string(&path) {
+            Ok(file) => {
+                let name = path.display().to_string();
+                let config = toml::from_str::<Self>(&file)
+                    .map_err(|e| Error::parse(&name, e.to_string()))?;
+                check_threshold(config.p_threshold)
+                    .map_err(|e| Error::parse(name, format!("p_threshold {e}")))?;
+                Ok(config)
+            },
+            Err(e) => match e.kind() {
+                std::io::ErrorKind::NotFound => Ok(Self::default()),
+                _ => Err(Error::cache(&path)(e)),
+            },
+        }
+    }
+}
@@ -1,5 +1,22 @@
-pub const THRESHOLD: f64 = 1e-5;
+use std::time::Duration;
+
+pub const DEFAULT_THRESHOLD: f64 = 1e-5;
+/// Lowest [`search_efo`](crate::query::search_efo) score worth showing.
+pub const EFO_SEARCH_MIN_SCORE: f64 = 0.4;
 pub const OWL_NS: &str = "http://www.w3.org/2002/07/owl#";
 pub const RDF_NS: &str = "http://www.w3.org/1999/02/22-rdf-syntax-ns#";
 pub const RDFS_NS: &str = "http://www.w3.org/2000/01/rdf-schema#";
 pub const OBO_IN_OWL_NS: &str = "http://www.geneontology.org/formats/oboInOwl#";
+pub const AZ_DATASETS: [&str; 3] = ["binary", "proteomics", "quantitative"];
+pub const AZ_COLUMNS: [&str; 3] = ["phenotype", "pValue", "GENE"];
+/// Start of every archive header, followed by [`ARCHIVE_VERSION`].
+pub const ARCHIVE_MAGIC: [u8; 8] = *b"SRCHGWAS";
+/// Bump whenever an archived type changes, so archives written by other
+/// versions are reprocessed instead of misread.
+pub const ARCHIVE_VERSION: u32 = 6;
+/// Padded to keep the archive after it 16-byte aligned.
+pub const ARCHIVE_HEADER_LEN: usize = 16;
+/// How long connecting to a server may take before it counts as unreachable.
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long a whole update check may take. Downloads have no limit.
+pub const CHECK_TIMEOUT: Duration = Duration::from_secs(30);
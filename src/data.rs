@@ -1,29 +1,496 @@
-use std::{collections::HashSet, hash::Hash};
+use std::{
+    collections::{HashMap, HashSet},
+    hash::Hash,
+};
 
 use chrono::{DateTime, Utc};
 use rkyv::{Archive, Deserialize, Serialize};
 
-use crate::consts::THRESHOLD;
+/// An ontology whose terms are annotated in the GWAS Catalog or imported into
+/// EFO. Terms of other ontologies are skipped when processing.
+#[derive(
+    Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Archive, Serialize, Deserialize,
+)]
+#[archive(check_bytes)]
+#[archive_attr(derive(Clone, Copy, PartialEq, Eq))]
+#[repr(u8)]
+pub enum Ontology {
+    Efo,
+    Mondo,
+    Hp,
+    Orphanet,
+    Go,
+    Oba,
+    Ncit,
+    Chebi,
+    Uberon,
+    Cl,
+    Pato,
+    Doid,
+    Mp,
+}
+
+impl Ontology {
+    /// Parses the prefix of a CURIE, ignoring case.
+    pub fn from_prefix(prefix: &str) -> Option<Self> {
+        Some(match prefix.to_ascii_uppercase().as_str() {
+            "EFO" => Self::Efo,
+            "MONDO" => Self::Mondo,
+            "HP" => Self::Hp,
+            "ORPHANET" => Self::Orphanet,
+            "GO" => Self::Go,
+            "OBA" => Self::Oba,
+            "NCIT" => Self::Ncit,
+            "CHEBI" => Self::Chebi,
+            "UBERON" => Self::Uberon,
+            "CL" => Self::Cl,
+            "PATO" => Self::Pato,
+            "DOID" => Self::Doid,
+            "MP" => Self::Mp,
+            _ => return None,
+        })
+    }
+
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::Efo => "EFO",
+            Self::Mondo => "MONDO",
+            Self::Hp => "HP",
+            Self::Orphanet => "Orphanet",
+            Self::Go => "GO",
+            Self::Oba => "OBA",
+            Self::Ncit => "NCIT",
+            Self::Chebi => "CHEBI",
+            Self::Uberon => "UBERON",
+            Self::Cl => "CL",
+            Self::Pato => "PATO",
+            Self::Doid => "DOID",
+            Self::Mp => "MP",
+        }
+    }
+
+    /// Letters before the number of a local id, e.g. the `C` of
+    /// `NCIT_C2985`.
+    #[inline]
+    fn local_prefix(self) -> &'static str {
+        match self {
+            Self::Ncit => "C",
+            _ => "",
+        }
+    }
+
+    /// Digits the number of a local id is zero padded to at least, whatever
+    /// it was written with.
+    #[inline]
+    fn width(self) -> usize {
+        match self {
+            Self::Orphanet | Self::Ncit | Self::Chebi | Self::Doid => 0,
+            _ => 7,
+        }
+    }
+
+    #[inline]
+    fn archived(self) -> ArchivedOntology {
+        match self {
+            Self::Efo => ArchivedOntology::Efo,
+            Self::Mondo => ArchivedOntology::Mondo,
+            Self::Hp => ArchivedOntology::Hp,
+            Self::Orphanet => ArchivedOntology::Orphanet,
+            Self::Go => ArchivedOntology::Go,
+            Self::Oba => ArchivedOntology::Oba,
+            Self::Ncit => ArchivedOntology::Ncit,
+            Self::Chebi => ArchivedOntology::Chebi,
+            Self::Uberon => ArchivedOntology::Uberon,
+            Self::Cl => ArchivedOntology::Cl,
+            Self::Pato => ArchivedOntology::Pato,
+            Self::Doid => ArchivedOntology::Doid,
+            Self::Mp => ArchivedOntology::Mp,
+        }
+    }
+}
+
+/// An ontology term, e.g. `EFO_0004705` or `MONDO_0005068`.
+#[derive(Clone, Copy, Debug, Archive, Serialize, Deserialize)]
+#[archive(check_bytes)]
+pub struct TermId {
+    pub ontology: Ontology,
+    pub id: u32,
+    /// Digits the local id was written with, so ids padded inconsistently
+    /// within an ontology, e.g. `DOID_0050890` next to `DOID_1612`, display
+    /// the way they were written. Not part of the identity of the term.
+    pub digits: u8,
+}
+
+impl TermId {
+    /// Parses a CURIE such as `EFO_0004705` or `mondo:0005068`, or a term URI
+    /// ending in one such as `http://www.ebi.ac.uk/efo/EFO_0004705`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let curie = s.trim().rsplit(['/', '#']).next()?;
+        let (prefix, local) = curie.split_once(['_', ':'])?;
+        let ontology = Ontology::from_prefix(prefix)?;
+        let local = match ontology.local_prefix() {
+            "" => local,
+            letters => local
+                .get(..letters.len())
+                .filter(|i| i.eq_ignore_ascii_case(letters))
+                .map(|_| &local[letters.len()..])?,
+        };
+        Some(Self {
+            ontology,
+            id: local.parse().ok()?,
+            digits: local.len().try_into().ok()?,
+        })
+    }
+
+    #[inline]
+    fn archived(self) -> ArchivedTermId {
+        ArchivedTermId {
+            ontology: self.ontology.archived(),
+            id: rkyv::Archived::<u32>::new(self.id),
+            digits: self.digits,
+        }
+    }
+}
+
+impl PartialEq for TermId {
+    fn eq(&self, other: &Self) -> bool {
+        self.ontology == other.ontology && self.id == other.id
+    }
+}
+
+impl Eq for TermId {}
+
+impl PartialOrd for TermId {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for TermId {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        (self.ontology, self.id).cmp(&(other.ontology, other.id))
+    }
+}
+
+impl PartialEq for ArchivedTermId {
+    fn eq(&self, other: &Self) -> bool {
+        self.ontology == other.ontology && self.id == other.id
+    }
+}
+
+impl Eq for ArchivedTermId {}
+
+// hashed the same as `ArchivedTermId`, so archived maps can be looked up with
+// an id
+impl Hash for TermId {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (self.ontology as u8).hash(state);
+        self.id.hash(state);
+    }
+}
+
+impl Hash for ArchivedTermId {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        (self.ontology as u8).hash(state);
+        self.id.value().hash(state);
+    }
+}
+
+impl From<&ArchivedTermId> for TermId {
+    #[inline]
+    fn from(id: &ArchivedTermId) -> Self {
+        id.deserialize(&mut rkyv::Infallible)
+            .expect("deserializing is infallible")
+    }
+}
+
+impl std::fmt::Display for TermId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}_{}{:0width$}",
+            self.ontology.prefix(),
+            self.ontology.local_prefix(),
+            self.id,
+            width = self.ontology.width().max(self.digits.into())
+        )
+    }
+}
 
 #[derive(Debug, Archive, Serialize, Deserialize, PartialEq, PartialOrd)]
+#[archive(check_bytes)]
 pub struct Association {
     // sorted
-    pub(crate) traits: Vec<u32>,
-    pub(crate) p_value: f64,
-    // uppercase, sorted
-    pub(crate) mapped_gene: Vec<String>,
-    pub(crate) accession_id: u32,
-    pub(crate) pubmed: u32,
+    pub traits: Vec<TermId>,
+    pub p_value: f64,
+    // uppercase, sorted, the genes the variant lies in
+    pub mapped_gene: Vec<String>,
+    // uppercase, the nearest genes either side of an intergenic variant,
+    // upstream first
+    pub flanking_gene: Vec<String>,
+    pub gene_context: GeneContext,
+    // Ensembl ids of the flanking genes
+    pub upstream_gene_id: Option<String>,
+    pub downstream_gene_id: Option<String>,
+    // base pairs to the flanking genes
+    pub upstream_gene_distance: Option<u32>,
+    pub downstream_gene_distance: Option<u32>,
+    // uppercase, sorted, from `REPORTED GENE(S)`
+    pub reported_gene: Vec<String>,
+    pub accession_id: u32,
+    pub pubmed: u32,
+    // lowercase, e.g. `rs123`, in the order listed in `SNPS`
+    pub snps: Vec<String>,
+    // e.g. `rs123-A`
+    pub strongest_snp_risk_allele: String,
+    pub snp_id_current: Option<u64>,
+    // GRCh38, one per position listed in `CHR_ID`/`CHR_POS`
+    pub locations: Vec<Location>,
+    // `OR or BETA`
+    pub effect: Option<f64>,
+    // bounds of `95% CI (TEXT)`
+    pub ci_lower: Option<f64>,
+    pub ci_upper: Option<f64>,
+    // text after the CI bounds, e.g. `unit increase`, empty for odds ratios
+    pub effect_unit: String,
+    pub risk_allele_frequency: Option<f64>,
+}
+
+/// Where a variant lies relative to its genes, from the separators in
+/// `MAPPED_GENE`.
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Archive,
+    Serialize,
+    Deserialize,
+)]
+#[archive(check_bytes)]
+pub enum GeneContext {
+    /// Within the listed genes, e.g. `COL5A2, PRKN`
+    #[default]
+    Intragenic,
+    /// Between an upstream and a downstream gene, e.g. `TSHR - LINC01234`
+    Intergenic,
+    /// An interaction between variants, e.g. `GENEA x GENEB`
+    Interaction,
+}
+
+/// Which genes of an association gene queries match against.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GeneSource {
+    /// Genes mapped by the catalog from the variant's position
+    #[default]
+    Mapped,
+    /// Genes named by the study's authors
+    Reported,
+    /// Either
+    Both,
 }
 
-#[derive(Debug, PartialEq, PartialOrd, serde::Serialize, serde::Deserialize)]
+impl GeneSource {
+    /// The lowercase name, as serialized.
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Mapped => "mapped",
+            Self::Reported => "reported",
+            Self::Both => "both",
+        }
+    }
+
+    /// Whether a gene found in `found` is searched by this source.
+    #[inline]
+    pub fn includes(self, found: Self) -> bool {
+        self.intersect(found).is_some()
+    }
+
+    /// The part of `found` searched by this source, e.g. `Mapped` for a gene
+    /// found in both when only mapped genes are searched.
+    #[inline]
+    pub fn intersect(self, found: Self) -> Option<Self> {
+        match (self, found) {
+            (Self::Both, found) => Some(found),
+            (searched, Self::Both) => Some(searched),
+            (searched, found) => (searched == found).then_some(found),
+        }
+    }
+
+    /// The source of a gene found in both `self` and `other`.
+    #[inline]
+    pub fn union(self, other: Self) -> Self {
+        if self == other {
+            self
+        } else {
+            Self::Both
+        }
+    }
+}
+
+impl std::fmt::Display for GeneSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Mapped => "mapped",
+            Self::Reported => "reported",
+            Self::Both => "mapped, reported",
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Risk,
+    Protective,
+}
+
+#[derive(Clone, Debug, Archive, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[archive(check_bytes)]
+pub struct Location {
+    // without a `chr` prefix, uppercase
+    pub chr: String,
+    pub pos: u32,
+}
+
+impl Location {
+    pub fn parse(chr: &str, pos: &str) -> Option<Self> {
+        Some(Self {
+            chr: normalize_chr(chr)?,
+            pos: pos.trim().parse().ok()?,
+        })
+    }
+}
+
+/// A 1-based, inclusive genomic interval.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct GenomicRegion {
+    pub chr: String,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl GenomicRegion {
+    /// Parses `chr:start-end`, e.g. `14:80,900,000-81,000,000` or
+    /// `chr14:80900000-81000000`.
+    pub fn parse(region: &str) -> Option<Self> {
+        let (chr, range) = region.trim().split_once(':')?;
+        let (start, end) = range.split_once('-')?;
+        let start = start.trim().replace(',', "").parse().ok()?;
+        let end = end.trim().replace(',', "").parse().ok()?;
+        if start > end {
+            return None;
+        }
+        Some(Self {
+            chr: normalize_chr(chr)?,
+            start,
+            end,
+        })
+    }
+
+    /// Parses a BED line, converting its 0-based, half-open interval.
+    pub fn parse_bed(line: &str) -> Option<Self> {
+        let mut fields = line.split('\t');
+        let chr = fields.next()?;
+        let start = fields.next()?.trim().parse::<u32>().ok()?;
+        let end = fields.next()?.trim().parse().ok()?;
+        if start >= end {
+            return None;
+        }
+        Some(Self {
+            chr: normalize_chr(chr)?,
+            start: start + 1,
+            end,
+        })
+    }
+
+    #[inline]
+    pub fn contains(&self, chr: &str, pos: u32) -> bool {
+        self.chr == chr && (self.start..=self.end).contains(&pos)
+    }
+}
+
+impl std::fmt::Display for GenomicRegion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}-{}", self.chr, self.start, self.end)
+    }
+}
+
+fn normalize_chr(chr: &str) -> Option<String> {
+    let chr = chr.trim();
+    let chr = chr
+        .strip_prefix("chr")
+        .or_else(|| chr.strip_prefix("CHR"))
+        .unwrap_or(chr)
+        .to_uppercase();
+    if chr.is_empty() {
+        None
+    } else {
+        Some(chr)
+    }
+}
+
+#[derive(
+    Debug,
+    PartialEq,
+    PartialOrd,
+    Archive,
+    Serialize,
+    Deserialize,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+#[archive(check_bytes)]
 pub struct AzAssociation {
     #[serde(rename = "phenotype")]
-    pub(crate) trait_: String,
+    pub trait_: String,
     #[serde(rename = "pValue")]
-    pub(crate) p_value: f64,
+    pub p_value: f64,
     #[serde(rename = "GENE")]
-    pub(crate) mapped_gene: String,
+    pub mapped_gene: String,
+    // set from the file the row was read from
+    #[serde(skip)]
+    pub dataset: AzDataset,
+}
+
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    PartialEq,
+    Eq,
+    PartialOrd,
+    Ord,
+    Hash,
+    Archive,
+    Serialize,
+    Deserialize,
+    serde::Serialize,
+)]
+#[archive(check_bytes)]
+#[serde(rename_all = "lowercase")]
+pub enum AzDataset {
+    #[default]
+    Binary,
+    Proteomics,
+    Quantitative,
+}
+
+impl std::fmt::Display for AzDataset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Binary => "binary",
+            Self::Proteomics => "proteomics",
+            Self::Quantitative => "quantitative",
+        })
+    }
 }
 
 impl Eq for Association {}
@@ -39,20 +506,389 @@ impl Hash for Association {
         self.traits.hash(state);
         self.p_value.to_bits().hash(state);
         self.mapped_gene.hash(state);
+        self.flanking_gene.hash(state);
+        self.gene_context.hash(state);
+        self.upstream_gene_id.hash(state);
+        self.downstream_gene_id.hash(state);
+        self.upstream_gene_distance.hash(state);
+        self.downstream_gene_distance.hash(state);
+        self.reported_gene.hash(state);
         self.accession_id.hash(state);
         self.pubmed.hash(state);
+        self.snps.hash(state);
+        self.strongest_snp_risk_allele.hash(state);
+        self.snp_id_current.hash(state);
+        self.locations.hash(state);
+        self.effect.map(f64::to_bits).hash(state);
+        self.ci_lower.map(f64::to_bits).hash(state);
+        self.ci_upper.map(f64::to_bits).hash(state);
+        self.effect_unit.hash(state);
+        self.risk_allele_frequency.map(f64::to_bits).hash(state);
+    }
+}
+
+/// Read access shared by [`Association`] and its zero-copy
+/// [`ArchivedAssociation`], so queries and output work over either.
+pub trait AssociationRecord {
+    fn traits(&self) -> impl Iterator<Item = TermId>;
+    fn p_value(&self) -> f64;
+    fn mapped_gene(&self) -> impl Iterator<Item = &str>;
+    fn flanking_gene(&self) -> impl Iterator<Item = &str>;
+    fn gene_context(&self) -> GeneContext;
+    fn upstream_gene_id(&self) -> Option<&str>;
+    fn downstream_gene_id(&self) -> Option<&str>;
+    fn upstream_gene_distance(&self) -> Option<u32>;
+    fn downstream_gene_distance(&self) -> Option<u32>;
+    fn reported_gene(&self) -> impl Iterator<Item = &str>;
+    fn accession_id(&self) -> u32;
+    fn pubmed(&self) -> u32;
+    fn snps(&self) -> impl Iterator<Item = &str>;
+    fn strongest_snp_risk_allele(&self) -> &str;
+    fn snp_id_current(&self) -> Option<u64>;
+    /// Chromosome and position of each location.
+    fn locations(&self) -> impl Iterator<Item = (&str, u32)>;
+    fn effect(&self) -> Option<f64>;
+    fn ci_lower(&self) -> Option<f64>;
+    fn ci_upper(&self) -> Option<f64>;
+    fn effect_unit(&self) -> &str;
+    fn risk_allele_frequency(&self) -> Option<f64>;
+
+    /// The study accession, e.g. `GCST000001`.
+    #[inline]
+    fn accession(&self) -> String {
+        format!("GCST{:06}", self.accession_id())
+    }
+
+    #[inline]
+    fn is_significant(&self, threshold: f64) -> bool {
+        self.p_value() < threshold
+    }
+
+    #[inline]
+    fn is_associated_with(&self, terms: &HashMap<TermId, &Efo>) -> bool {
+        self.traits().any(|i| terms.contains_key(&i))
+    }
+
+    /// The genes of `source`, the mapped ones followed by those flanking the
+    /// variant if `flanking` is set and then the reported ones. Genes can
+    /// repeat across sources.
+    #[inline]
+    fn genes(&self, source: GeneSource, flanking: bool) -> impl Iterator<Item = &str> {
+        let mapped = source != GeneSource::Reported;
+        let reported = source != GeneSource::Mapped;
+        self.mapped_gene()
+            .filter(move |_| mapped)
+            .chain(self.flanking_gene().filter(move |_| mapped && flanking))
+            .chain(self.reported_gene().filter(move |_| reported))
+    }
+
+    /// Which genes of this association list `gene`, flanking genes counting
+    /// as mapped if `flanking` is set.
+    fn gene_source(&self, gene: &str, flanking: bool) -> Option<GeneSource> {
+        let mapped = self.genes(GeneSource::Mapped, flanking).any(|i| i == gene);
+        let reported = self.reported_gene().any(|i| i == gene);
+        match (mapped, reported) {
+            (true, true) => Some(GeneSource::Both),
+            (true, false) => Some(GeneSource::Mapped),
+            (false, true) => Some(GeneSource::Reported),
+            (false, false) => None,
+        }
+    }
+
+    #[inline]
+    fn has_gene(&self, gene: &str, source: GeneSource, flanking: bool) -> bool {
+        self.gene_source(gene, flanking)
+            .is_some_and(|found| source.includes(found))
+    }
+
+    /// Whether this association was reported for the given lowercase
+    /// variant, either by its listed ID or by its current rsID.
+    fn has_variant(&self, variant: &str) -> bool {
+        self.snps().any(|i| i == variant)
+            || variant
+                .strip_prefix("rs")
+                .and_then(|i| i.parse::<u64>().ok())
+                .is_some_and(|i| self.snp_id_current() == Some(i))
+    }
+
+    #[inline]
+    fn is_in_region(&self, region: &GenomicRegion) -> bool {
+        self.locations().any(|(chr, pos)| region.contains(chr, pos))
+    }
+
+    /// The allele part of `STRONGEST SNP-RISK ALLELE`, e.g. `A` for
+    /// `rs123-A`.
+    fn risk_allele(&self) -> &str {
+        self.strongest_snp_risk_allele()
+            .rsplit_once('-')
+            .map_or("", |(_, allele)| allele)
+    }
+
+    /// Whether the effect is an odds ratio: positive, without unit text and
+    /// within its confidence interval. A beta's CI text usually names its
+    /// unit, but an effect with neither a unit nor a CI could be either.
+    fn is_odds_ratio(&self) -> bool {
+        match (self.effect(), self.ci_lower(), self.ci_upper()) {
+            (Some(effect), Some(lower), Some(upper)) => {
+                self.effect_unit().is_empty() && lower > 0.0 && lower <= effect && effect <= upper
+            },
+            _ => false,
+        }
+    }
+
+    /// Whether the risk allele raises or lowers the odds or value of the
+    /// trait, inferred from the odds ratio or the beta's unit text. `None`
+    /// if the effect type is ambiguous.
+    fn direction(&self) -> Option<Direction> {
+        let effect = self.effect()?;
+        let unit = self.effect_unit();
+        if self.is_odds_ratio() {
+            if effect > 1.0 {
+                Some(Direction::Risk)
+            } else if effect < 1.0 {
+                Some(Direction::Protective)
+            } else {
+                None
+            }
+        } else if unit.contains("decrease") {
+            Some(Direction::Protective)
+        } else if unit.contains("increase") {
+            Some(Direction::Risk)
+        } else {
+            None
+        }
+    }
+
+    /// Effect size comparable across directions: odds ratios below 1 are
+    /// inverted and betas are taken as absolute values. `None` if the effect
+    /// type is ambiguous.
+    fn effect_magnitude(&self) -> Option<f64> {
+        let effect = self.effect()?;
+        if self.is_odds_ratio() {
+            Some(if effect < 1.0 { 1.0 / effect } else { effect })
+        } else if !self.effect_unit().is_empty() {
+            Some(effect.abs())
+        } else {
+            None
+        }
+    }
+}
+
+impl AssociationRecord for Association {
+    #[inline]
+    fn traits(&self) -> impl Iterator<Item = TermId> {
+        self.traits.iter().copied()
+    }
+
+    #[inline]
+    fn p_value(&self) -> f64 {
+        self.p_value
+    }
+
+    #[inline]
+    fn mapped_gene(&self) -> impl Iterator<Item = &str> {
+        self.mapped_gene.iter().map(String::as_str)
+    }
+
+    #[inline]
+    fn flanking_gene(&self) -> impl Iterator<Item = &str> {
+        self.flanking_gene.iter().map(String::as_str)
+    }
+
+    #[inline]
+    fn gene_context(&self) -> GeneContext {
+        self.gene_context
+    }
+
+    #[inline]
+    fn upstream_gene_id(&self) -> Option<&str> {
+        self.upstream_gene_id.as_deref()
+    }
+
+    #[inline]
+    fn downstream_gene_id(&self) -> Option<&str> {
+        self.downstream_gene_id.as_deref()
+    }
+
+    #[inline]
+    fn upstream_gene_distance(&self) -> Option<u32> {
+        self.upstream_gene_distance
+    }
+
+    #[inline]
+    fn downstream_gene_distance(&self) -> Option<u32> {
+        self.downstream_gene_distance
+    }
+
+    #[inline]
+    fn reported_gene(&self) -> impl Iterator<Item = &str> {
+        self.reported_gene.iter().map(String::as_str)
+    }
+
+    #[inline]
+    fn accession_id(&self) -> u32 {
+        self.accession_id
+    }
+
+    #[inline]
+    fn pubmed(&self) -> u32 {
+        self.pubmed
+    }
+
+    #[inline]
+    fn snps(&self) -> impl Iterator<Item = &str> {
+        self.snps.iter().map(String::as_str)
+    }
+
+    #[inline]
+    fn strongest_snp_risk_allele(&self) -> &str {
+        &self.strongest_snp_risk_allele
+    }
+
+    #[inline]
+    fn snp_id_current(&self) -> Option<u64> {
+        self.snp_id_current
+    }
+
+    #[inline]
+    fn locations(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.locations.iter().map(|i| (i.chr.as_str(), i.pos))
+    }
+
+    #[inline]
+    fn effect(&self) -> Option<f64> {
+        self.effect
+    }
+
+    #[inline]
+    fn ci_lower(&self) -> Option<f64> {
+        self.ci_lower
+    }
+
+    #[inline]
+    fn ci_upper(&self) -> Option<f64> {
+        self.ci_upper
+    }
+
+    #[inline]
+    fn effect_unit(&self) -> &str {
+        &self.effect_unit
+    }
+
+    #[inline]
+    fn risk_allele_frequency(&self) -> Option<f64> {
+        self.risk_allele_frequency
     }
 }
 
-impl Association {
+impl AssociationRecord for ArchivedAssociation {
+    #[inline]
+    fn traits(&self) -> impl Iterator<Item = TermId> {
+        self.traits.iter().map(TermId::from)
+    }
+
+    #[inline]
+    fn p_value(&self) -> f64 {
+        self.p_value.value()
+    }
+
+    #[inline]
+    fn mapped_gene(&self) -> impl Iterator<Item = &str> {
+        self.mapped_gene.iter().map(|i| i.as_str())
+    }
+
+    #[inline]
+    fn flanking_gene(&self) -> impl Iterator<Item = &str> {
+        self.flanking_gene.iter().map(|i| i.as_str())
+    }
+
+    #[inline]
+    fn gene_context(&self) -> GeneContext {
+        self.gene_context
+            .deserialize(&mut rkyv::Infallible)
+            .expect("deserializing is infallible")
+    }
+
+    #[inline]
+    fn upstream_gene_id(&self) -> Option<&str> {
+        self.upstream_gene_id.as_ref().map(|i| i.as_str())
+    }
+
+    #[inline]
+    fn downstream_gene_id(&self) -> Option<&str> {
+        self.downstream_gene_id.as_ref().map(|i| i.as_str())
+    }
+
+    #[inline]
+    fn upstream_gene_distance(&self) -> Option<u32> {
+        self.upstream_gene_distance.as_ref().map(|i| i.value())
+    }
+
+    #[inline]
+    fn downstream_gene_distance(&self) -> Option<u32> {
+        self.downstream_gene_distance.as_ref().map(|i| i.value())
+    }
+
+    #[inline]
+    fn reported_gene(&self) -> impl Iterator<Item = &str> {
+        self.reported_gene.iter().map(|i| i.as_str())
+    }
+
+    #[inline]
+    fn accession_id(&self) -> u32 {
+        self.accession_id.value()
+    }
+
+    #[inline]
+    fn pubmed(&self) -> u32 {
+        self.pubmed.value()
+    }
+
+    #[inline]
+    fn snps(&self) -> impl Iterator<Item = &str> {
+        self.snps.iter().map(|i| i.as_str())
+    }
+
+    #[inline]
+    fn strongest_snp_risk_allele(&self) -> &str {
+        &self.strongest_snp_risk_allele
+    }
+
+    #[inline]
+    fn snp_id_current(&self) -> Option<u64> {
+        self.snp_id_current.as_ref().map(|i| i.value())
+    }
+
     #[inline]
-    pub fn is_significant(&self) -> bool {
-        self.p_value < THRESHOLD
+    fn locations(&self) -> impl Iterator<Item = (&str, u32)> {
+        self.locations
+            .iter()
+            .map(|i| (i.chr.as_str(), i.pos.value()))
     }
 
     #[inline]
-    pub fn is_associated_with(&self, efo: u32) -> bool {
-        self.traits.contains(&efo)
+    fn effect(&self) -> Option<f64> {
+        self.effect.as_ref().map(|i| i.value())
+    }
+
+    #[inline]
+    fn ci_lower(&self) -> Option<f64> {
+        self.ci_lower.as_ref().map(|i| i.value())
+    }
+
+    #[inline]
+    fn ci_upper(&self) -> Option<f64> {
+        self.ci_upper.as_ref().map(|i| i.value())
+    }
+
+    #[inline]
+    fn effect_unit(&self) -> &str {
+        &self.effect_unit
+    }
+
+    #[inline]
+    fn risk_allele_frequency(&self) -> Option<f64> {
+        self.risk_allele_frequency.as_ref().map(|i| i.value())
     }
 }
 
@@ -69,13 +905,14 @@ impl Hash for AzAssociation {
         self.trait_.hash(state);
         self.p_value.to_bits().hash(state);
         self.mapped_gene.hash(state);
+        self.dataset.hash(state);
     }
 }
 
 impl AzAssociation {
     #[inline]
-    pub fn is_significant(&self) -> bool {
-        self.p_value < THRESHOLD
+    pub fn is_significant(&self, threshold: f64) -> bool {
+        self.p_value < threshold
     }
 
     #[inline]
@@ -85,14 +922,21 @@ impl AzAssociation {
 }
 
 #[derive(Clone, Debug, Archive, Serialize, Deserialize)]
+#[archive(check_bytes)]
 pub struct Efo {
-    pub(crate) id: u32,
+    // usually an `EFO_` term, but EFO also imports terms from MONDO, HP and
+    // other ontologies
+    pub id: TermId,
     // uppercase
-    pub(crate) label: String,
-    pub(crate) parent: Option<u32>,
-    pub(crate) children: HashSet<u32>,
+    pub label: String,
+    // sorted, named `rdfs:subClassOf` parents only
+    pub parents: Vec<TermId>,
+    // anonymous `owl:Restriction` superclasses with a recognised property
+    pub relations: Vec<Relation>,
+    // inverse of `parents`
+    pub children: HashSet<TermId>,
     // uppercase
-    pub(crate) synonyms: HashSet<String>,
+    pub synonyms: HashSet<String>,
 }
 
 impl Hash for Efo {
@@ -109,7 +953,485 @@ impl PartialEq for Efo {
 
 impl Eq for Efo {}
 
+#[derive(Clone, Copy, Debug, Archive, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[archive(check_bytes)]
+pub enum RelationKind {
+    PartOf,
+}
+
+impl RelationKind {
+    pub fn from_property(property: &str) -> Option<Self> {
+        match property {
+            "BFO_0000050" => Some(Self::PartOf),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Archive, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[archive(check_bytes)]
+pub struct Relation {
+    pub kind: RelationKind,
+    pub target: TermId,
+}
+
 #[derive(Debug, Archive, Serialize, Deserialize)]
+#[archive(check_bytes)]
 pub struct Metadata {
-    pub(crate) last_updated: DateTime<Utc>,
+    pub last_updated: DateTime<Utc>,
+}
+
+/// Secondary indexes into the associations archive, built alongside it so
+/// lookups don't scan every association.
+#[derive(Debug, Default, Archive, Serialize, Deserialize)]
+#[archive(check_bytes)]
+pub struct Index {
+    // number of associations indexed, to detect a stale index
+    pub len: u32,
+    // term -> offsets of the associations annotated with it
+    pub traits: HashMap<TermId, Vec<u32>>,
+    // uppercase mapped, flanking or reported gene -> offsets
+    pub genes: HashMap<String, Vec<u32>>,
+    // lowercase variant, both as listed in `SNPS` and as `rs<SNP_ID_CURRENT>`
+    // -> offsets
+    pub variants: HashMap<String, Vec<u32>>,
+}
+
+impl Index {
+    pub fn build(associations: &[impl AssociationRecord]) -> Self {
+        let mut index = Self {
+            len: associations.len() as u32,
+            ..Default::default()
+        };
+        for (offset, assoc) in associations.iter().enumerate() {
+            let offset = offset as u32;
+            for id in assoc.traits() {
+                index.traits.entry(id).or_default().push(offset);
+            }
+            for gene in assoc.genes(GeneSource::Both, true) {
+                index
+                    .genes
+                    .entry(gene.to_string())
+                    .or_default()
+                    .push(offset);
+            }
+            let current = assoc.snp_id_current().map(|i| format!("rs{i}"));
+            for variant in assoc.snps().chain(current.as_deref()) {
+                index
+                    .variants
+                    .entry(variant.to_string())
+                    .or_default()
+                    .push(offset);
+            }
+        }
+        // offsets are pushed in order, so this drops repeats within an
+        // association, e.g. a current rsID that is also listed
+        index.traits.values_mut().for_each(Vec::dedup);
+        index.genes.values_mut().for_each(Vec::dedup);
+        index.variants.values_mut().for_each(Vec::dedup);
+        index
+    }
+}
+
+impl ArchivedIndex {
+    /// Sorted offsets of the associations annotated with any of `terms`.
+    pub fn trait_offsets(&self, terms: impl Iterator<Item = TermId>) -> Vec<usize> {
+        let mut offsets = terms
+            .filter_map(|i| self.traits.get(&i.archived()))
+            .flat_map(|i| i.iter().map(|i| i.value() as usize))
+            .collect::<Vec<_>>();
+        offsets.sort_unstable();
+        offsets.dedup();
+        offsets
+    }
+
+    /// Sorted offsets of the associations mapped to, flanked by or reported
+    /// for the uppercase `gene`.
+    pub fn gene_offsets(&self, gene: &str) -> Vec<usize> {
+        self.genes
+            .get(gene)
+            .map(|i| i.iter().map(|i| i.value() as usize).collect())
+            .unwrap_or_default()
+    }
+
+    /// Sorted offsets of the associations reported for the lowercase
+    /// `variant`.
+    pub fn variant_offsets(&self, variant: &str) -> Vec<usize> {
+        self.variants
+            .get(variant)
+            .map(|i| i.iter().map(|i| i.value() as usize).collect())
+            .unwrap_or_default()
+    }
+}
+
+/// What a gene given by the user was recognised as, see [`Hgnc::resolve`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    PreviousSymbol,
+    Alias,
+    HgncId,
+    EnsemblId,
+    EntrezId,
+}
+
+impl std::fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::PreviousSymbol => "previous symbol",
+            Self::Alias => "alias",
+            Self::HgncId => "HGNC ID",
+            Self::EnsemblId => "Ensembl ID",
+            Self::EntrezId => "Entrez ID",
+        })
+    }
+}
+
+/// The approved symbol an uppercase gene resolves to, see [`Hgnc::resolve`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GeneResolution {
+    /// Already an approved symbol
+    Approved,
+    Resolved {
+        symbol: String,
+        kind: SymbolKind,
+    },
+    /// A previous symbol or alias of several genes
+    Ambiguous {
+        symbols: Vec<String>,
+        kind: SymbolKind,
+    },
+    Unknown,
+}
+
+/// Approved HGNC gene symbols and the identifiers, previous symbols and
+/// aliases that resolve to them, from an imported HGNC complete set. Every
+/// symbol is uppercase, like the genes of the associations.
+#[derive(Debug, Default, Archive, Serialize, Deserialize)]
+#[archive(check_bytes)]
+pub struct Hgnc {
+    pub approved: HashSet<String>,
+    // uppercase HGNC, Ensembl or Entrez ID -> symbol
+    pub ids: HashMap<String, String>,
+    // previous symbol -> symbols, sorted
+    pub previous: HashMap<String, Vec<String>>,
+    // alias -> symbols, sorted
+    pub aliases: HashMap<String, Vec<String>>,
+}
+
+impl Hgnc {
+    /// Resolves an uppercase gene symbol or identifier to its approved
+    /// symbol. Approved symbols take precedence over identifiers, then
+    /// previous symbols and then aliases, as the same string can be the
+    /// approved symbol of one gene and an alias of another.
+    pub fn resolve(&self, gene: &str) -> GeneResolution {
+        if self.approved.contains(gene) {
+            return GeneResolution::Approved;
+        }
+        if let Some(symbol) = self.ids.get(gene) {
+            let kind = if gene.starts_with("HGNC:") {
+                SymbolKind::HgncId
+            } else if gene.starts_with("ENSG") {
+                SymbolKind::EnsemblId
+            } else {
+                SymbolKind::EntrezId
+            };
+            return GeneResolution::Resolved {
+                symbol: symbol.clone(),
+                kind,
+            };
+        }
+        for (symbols, kind) in [
+            (&self.previous, SymbolKind::PreviousSymbol),
+            (&self.aliases, SymbolKind::Alias),
+        ] {
+            match symbols.get(gene).map(Vec::as_slice) {
+                Some([symbol]) => {
+                    return GeneResolution::Resolved {
+                        symbol: symbol.clone(),
+                        kind,
+                    }
+                },
+                Some(symbols) => {
+                    return GeneResolution::Ambiguous {
+                        symbols: symbols.to_vec(),
+                        kind,
+                    }
+                },
+                None => {},
+            }
+        }
+        GeneResolution::Unknown
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A significant association with only the given terms and genes.
+    pub(crate) fn association(traits: &[&str], mapped: &[&str], reported: &[&str]) -> Association {
+        let genes = |genes: &[&str]| genes.iter().map(|i| i.to_string()).collect();
+        Association {
+            traits: traits.iter().map(|i| TermId::parse(i).unwrap()).collect(),
+            p_value: 1e-10,
+            mapped_gene: genes(mapped),
+            flanking_gene: Vec::new(),
+            gene_context: GeneContext::Intragenic,
+            upstream_gene_id: None,
+            downstream_gene_id: None,
+            upstream_gene_distance: None,
+            downstream_gene_distance: None,
+            reported_gene: genes(reported),
+            accession_id: 1,
+            pubmed: 1,
+            snps: Vec::new(),
+            strongest_snp_risk_allele: String::new(),
+            snp_id_current: None,
+            locations: Vec::new(),
+            effect: None,
+            ci_lower: None,
+            ci_upper: None,
+            effect_unit: String::new(),
+            risk_allele_frequency: None,
+        }
+    }
+
+    #[test]
+    fn term_id_curie() {
+        let id = TermId::parse("EFO_0004705").unwrap();
+        assert_eq!((id.ontology, id.id), (Ontology::Efo, 4705));
+        assert_eq!(TermId::parse("efo:0004705"), Some(id));
+        assert_eq!(TermId::parse(" EFO_4705 "), Some(id));
+    }
+
+    #[test]
+    fn term_id_uri() {
+        assert_eq!(
+            TermId::parse("http://www.ebi.ac.uk/efo/EFO_0004705"),
+            TermId::parse("EFO_0004705")
+        );
+        assert_eq!(
+            TermId::parse("http://www.orpha.net/ORDO/Orphanet_101435").map(|i| i.to_string()),
+            Some("Orphanet_101435".to_string())
+        );
+    }
+
+    #[test]
+    fn term_id_invalid() {
+        assert_eq!(TermId::parse("XYZ_0004705"), None);
+        assert_eq!(TermId::parse("EFO_"), None);
+        assert_eq!(TermId::parse("EFO0004705"), None);
+        assert_eq!(TermId::parse("NCIT_2985"), None);
+    }
+
+    #[test]
+    fn term_id_display() {
+        for id in [
+            "EFO_0004705",
+            "MONDO_0005068",
+            "NCIT_C2985",
+            "CHEBI_15377",
+            "DOID_0050890",
+            "DOID_1612",
+        ] {
+            assert_eq!(TermId::parse(id).unwrap().to_string(), id);
+        }
+        assert_eq!(
+            TermId::parse("mondo:5068").unwrap().to_string(),
+            "MONDO_0005068"
+        );
+        assert_eq!(
+            TermId::parse("ncit:c2985").unwrap().to_string(),
+            "NCIT_C2985"
+        );
+    }
+
+    #[test]
+    fn gene_source_intersect() {
+        use GeneSource::*;
+        assert_eq!(Mapped.intersect(Both), Some(Mapped));
+        assert_eq!(Reported.intersect(Both), Some(Reported));
+        assert_eq!(Both.intersect(Reported), Some(Reported));
+        assert_eq!(Both.intersect(Both), Some(Both));
+        assert_eq!(Mapped.intersect(Reported), None);
+    }
+
+    #[test]
+    fn region() {
+        let region = GenomicRegion {
+            chr: "14".to_string(),
+            start: 80_900_000,
+            end: 81_000_000,
+        };
+        assert_eq!(
+            GenomicRegion::parse("14:80,900,000-81,000,000"),
+            Some(region.clone())
+        );
+        assert_eq!(
+            GenomicRegion::parse("chr14:80900000-81000000"),
+            Some(region)
+        );
+        assert_eq!(
+            GenomicRegion::parse("chrx:1-1").map(|i| i.chr),
+            Some("X".to_string())
+        );
+    }
+
+    #[test]
+    fn region_invalid() {
+        assert_eq!(GenomicRegion::parse("14:81000000-80900000"), None);
+        assert_eq!(GenomicRegion::parse("14:80900000"), None);
+        assert_eq!(GenomicRegion::parse("chr:1-2"), None);
+        assert_eq!(GenomicRegion::parse("14:a-b"), None);
+    }
+
+    #[test]
+    fn region_bed() {
+        assert_eq!(
+            GenomicRegion::parse_bed("chr14\t80899999\t81000000\tname"),
+            GenomicRegion::parse("14:80900000-81000000")
+        );
+        assert_eq!(GenomicRegion::parse_bed("chr14\t100\t100"), None);
+    }
+
+    #[test]
+    fn index_dedups_within_an_association() {
+        let associations = vec![
+            Association {
+                snps: vec!["rs1".to_string()],
+                snp_id_current: Some(1),
+                ..association(&["EFO_0000001"], &["TSHR"], &["TSHR"])
+            },
+            Association {
+                snps: vec!["rs1".to_string(), "rs2".to_string()],
+                snp_id_current: Some(2),
+                ..association(&["EFO_0000001", "EFO_0000002"], &["TSHR", "PRKN"], &[])
+            },
+        ];
+        let index = Index::build(&associations);
+        assert_eq!(index.len, 2);
+        assert_eq!(index.variants["rs1"], [0, 1]);
+        assert_eq!(index.variants["rs2"], [1]);
+        assert_eq!(index.genes["TSHR"], [0, 1]);
+        assert_eq!(index.genes["PRKN"], [1]);
+        assert_eq!(index.traits[&TermId::parse("EFO_0000001").unwrap()], [0, 1]);
+        assert_eq!(index.traits[&TermId::parse("EFO_0000002").unwrap()], [1]);
+    }
+
+    fn hgnc() -> Hgnc {
+        let map = |entries: &[(&str, &[&str])]| {
+            entries
+                .iter()
+                .map(|(key, symbols)| {
+                    (
+                        key.to_string(),
+                        symbols.iter().map(|i| i.to_string()).collect(),
+                    )
+                })
+                .collect()
+        };
+        Hgnc {
+            approved: ["PRKN", "TSHR", "AAA", "BBB"].map(String::from).into(),
+            ids: [
+                ("HGNC:8607", "PRKN"),
+                ("ENSG00000185345", "PRKN"),
+                ("5071", "PRKN"),
+            ]
+            .map(|(id, symbol)| (id.to_string(), symbol.to_string()))
+            .into(),
+            previous: map(&[
+                ("PARK2", &["PRKN"]),
+                ("OLD", &["AAA"]),
+                ("GONE", &["AAA", "BBB"]),
+            ]),
+            aliases: map(&[
+                ("TSHR", &["AAA"]),
+                ("OLD", &["BBB"]),
+                ("SHARED", &["AAA", "BBB"]),
+            ]),
+        }
+    }
+
+    fn resolved(symbol: &str, kind: SymbolKind) -> GeneResolution {
+        GeneResolution::Resolved {
+            symbol: symbol.to_string(),
+            kind,
+        }
+    }
+
+    #[test]
+    fn hgnc_resolve() {
+        let hgnc = hgnc();
+        assert_eq!(hgnc.resolve("PRKN"), GeneResolution::Approved);
+        assert_eq!(
+            hgnc.resolve("PARK2"),
+            resolved("PRKN", SymbolKind::PreviousSymbol)
+        );
+        assert_eq!(
+            hgnc.resolve("HGNC:8607"),
+            resolved("PRKN", SymbolKind::HgncId)
+        );
+        assert_eq!(
+            hgnc.resolve("ENSG00000185345"),
+            resolved("PRKN", SymbolKind::EnsemblId)
+        );
+        assert_eq!(hgnc.resolve("5071"), resolved("PRKN", SymbolKind::EntrezId));
+        assert_eq!(hgnc.resolve("NOPE"), GeneResolution::Unknown);
+    }
+
+    #[test]
+    fn hgnc_resolve_precedence() {
+        let hgnc = hgnc();
+        // an approved symbol of one gene and an alias of another
+        assert_eq!(hgnc.resolve("TSHR"), GeneResolution::Approved);
+        // a previous symbol of one gene and an alias of another
+        assert_eq!(
+            hgnc.resolve("OLD"),
+            resolved("AAA", SymbolKind::PreviousSymbol)
+        );
+    }
+
+    #[test]
+    fn hgnc_resolve_ambiguous() {
+        let hgnc = hgnc();
+        let ambiguous = |kind| GeneResolution::Ambiguous {
+            symbols: vec!["AAA".to_string(), "BBB".to_string()],
+            kind,
+        };
+        assert_eq!(hgnc.resolve("GONE"), ambiguous(SymbolKind::PreviousSymbol));
+        assert_eq!(hgnc.resolve("SHARED"), ambiguous(SymbolKind::Alias));
+    }
+
+    #[test]
+    fn effect_odds_ratio() {
+        let mut assoc = association(&[], &[], &[]);
+        (assoc.effect, assoc.ci_lower, assoc.ci_upper) = (Some(0.5), Some(0.4), Some(0.6));
+        assert!(assoc.is_odds_ratio());
+        assert_eq!(assoc.direction(), Some(Direction::Protective));
+        assert_eq!(assoc.effect_magnitude(), Some(2.0));
+    }
+
+    #[test]
+    fn effect_beta() {
+        let mut assoc = association(&[], &[], &[]);
+        (assoc.effect, assoc.ci_lower, assoc.ci_upper) = (Some(0.3), Some(0.2), Some(0.4));
+        assoc.effect_unit = "unit decrease".to_string();
+        assert!(!assoc.is_odds_ratio());
+        assert_eq!(assoc.direction(), Some(Direction::Protective));
+        assert_eq!(assoc.effect_magnitude(), Some(0.3));
+    }
+
+    #[test]
+    fn effect_ambiguous() {
+        // without a unit or a CI
+        let mut assoc = association(&[], &[], &[]);
+        assoc.effect = Some(0.5);
+        assert!(!assoc.is_odds_ratio());
+        assert_eq!(assoc.direction(), None);
+        assert_eq!(assoc.effect_magnitude(), None);
+        // without a unit, outside its CI
+        (assoc.ci_lower, assoc.ci_upper) = (Some(0.01), Some(0.03));
+        assert_eq!(assoc.direction(), None);
+        assert_eq!(assoc.effect_magnitude(), None);
+    }
 }
@@ -0,0 +1,147 @@
+use std::path::PathBuf;
+
+/// Everything that can go wrong fetching, processing or loading the data,
+/// grouped into classes that each exit with their own code.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error(
+        "failed to reach {url}: {source}\nCheck your internet connection and try again, or pass \
+         --offline or set SEARCH_GWAS_OFFLINE=1 to only use the data already downloaded"
+    )]
+    Network {
+        url: String,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error(
+        "unexpected format in {name}: {reason}\nIf this was downloaded, the upstream format may \
+         have changed, please report this at https://github.com/mrvillage/search-gwas/issues"
+    )]
+    Schema { name: String, reason: String },
+    #[error("failed to parse {name}: {reason}")]
+    Parse { name: String, reason: String },
+    #[error("failed to access {}: {source}", path.display())]
+    Cache {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error(
+        "{} is missing or corrupt\n{}",
+        path.display(),
+        if *pinned {
+            "Run `search-gwas update --reprocess` to rebuild it from the imported data, or \
+             `search-gwas import` the files again"
+        } else {
+            "Run `search-gwas update --force --force` to rebuild it"
+        }
+    )]
+    CorruptCache {
+        path: PathBuf,
+        // whether the data was imported, which a forced update would replace
+        pinned: bool,
+    },
+    #[error(
+        "{} does not exist and can't be downloaded in offline mode\nRun `search-gwas import` with a \
+         local copy of the GWAS Catalog and EFO, or `search-gwas update` on a machine with network \
+         access and copy its data directory to {}",
+        path.display(),
+        path.parent().unwrap_or(path).display()
+    )]
+    Offline { path: PathBuf },
+    #[error("failed to interact with the terminal: {0}")]
+    Terminal(#[source] std::io::Error),
+    #[error("failed to read {name}: {source}")]
+    Io {
+        name: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error(
+        "{url} can't be downloaded in offline mode\nPass a local path instead, or run without \
+         --offline and SEARCH_GWAS_OFFLINE"
+    )]
+    DownloadDisabled { url: String },
+    #[error("\"{term}\" is not in the EFO archive{}", did_you_mean(suggestions))]
+    UnknownTrait {
+        term: String,
+        // the closest labels, if any
+        suggestions: Vec<String>,
+    },
+    /// Arguments that are invalid together or with the config file, which
+    /// clap can't check.
+    #[error("{0}")]
+    Usage(String),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+fn did_you_mean(suggestions: &[String]) -> String {
+    if suggestions.is_empty() {
+        return String::new();
+    }
+    let lines = suggestions.iter().map(|i| format!("\n  {i}"));
+    format!(", did you mean:{}", lines.collect::<String>())
+}
+
+impl Error {
+    /// The process exit code for this class of error. `2` is shared with the
+    /// invalid arguments clap rejects and panics exit with `101`.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::Network { .. } => 3,
+            Self::Schema { .. } => 4,
+            Self::Parse { .. } => 5,
+            Self::Cache { .. } | Self::CorruptCache { .. } | Self::Offline { .. } => 6,
+            Self::Terminal(_) => 7,
+            Self::Io { .. } => 8,
+            Self::Usage(_) => 2,
+            Self::DownloadDisabled { .. } => 9,
+            Self::UnknownTrait { .. } => 10,
+        }
+    }
+
+    pub(crate) fn network(url: &str) -> impl FnOnce(reqwest::Error) -> Self + '_ {
+        move |source| Self::Network {
+            url: url.to_string(),
+            source,
+        }
+    }
+
+    pub fn cache(path: &std::path::Path) -> impl FnOnce(std::io::Error) -> Self + '_ {
+        move |source| Self::Cache {
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    pub(crate) fn corrupt_cache(path: &std::path::Path) -> Self {
+        Self::CorruptCache {
+            path: path.to_path_buf(),
+            pinned: path
+                .parent()
+                .is_some_and(|dir| crate::files::pinned_path(dir).exists()),
+        }
+    }
+
+    /// For input files given on the command line, as opposed to the data
+    /// directory.
+    pub fn io(name: impl Into<String>) -> impl FnOnce(std::io::Error) -> Self {
+        let name = name.into();
+        move |source| Self::Io { name, source }
+    }
+
+    pub(crate) fn schema(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::Schema {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn parse(name: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::Parse {
+            name: name.into(),
+            reason: reason.into(),
+        }
+    }
+}
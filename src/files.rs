@@ -2,71 +2,96 @@ use std::{
     collections::{HashMap, HashSet},
     env::{current_dir, temp_dir},
     fs::File,
-    io::Write,
+    io::{Read, Write},
     path::{Path, PathBuf},
 };
 
 use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use csv::DeserializeRecordsIntoIter;
 use flate2::read::GzDecoder;
+use memmap2::Mmap;
 use rand::{distributions::Alphanumeric, thread_rng, Rng};
-use rayon::iter::{plumbing::Folder, ParallelBridge, ParallelIterator};
+use rayon::iter::{IntoParallelIterator, ParallelBridge, ParallelIterator};
 use reqwest::blocking::{Client, ClientBuilder};
-use rkyv::ser::serializers::AllocSerializer;
+use rkyv::{
+    ser::serializers::AllocSerializer, validation::validators::DefaultValidator, vec::ArchivedVec,
+    AlignedVec, Archive, CheckBytes, Deserialize,
+};
 
 use crate::{
-    consts::{OBO_IN_OWL_NS, OWL_NS, RDFS_NS, RDF_NS},
-    data::{Association, AzAssociation, Efo, Metadata},
+    consts::{
+        ARCHIVE_HEADER_LEN, ARCHIVE_MAGIC, ARCHIVE_VERSION, AZ_COLUMNS, AZ_DATASETS, CHECK_TIMEOUT,
+        CONNECT_TIMEOUT, OBO_IN_OWL_NS, OWL_NS, RDFS_NS, RDF_NS,
+    },
+    data::{
+        ArchivedAssociation, ArchivedIndex, Association, AzAssociation, AzDataset, Efo,
+        GeneContext, Hgnc, Index, Location, Metadata, Relation, RelationKind, TermId,
+    },
+    error::{Error, Result},
 };
 
+const GWAS_URL: &str = "https://www.ebi.ac.uk/gwas/api/search/downloads/alternative";
+const EFO_URL: &str = "https://www.ebi.ac.uk/efo/efo.owl";
+
+/// Sends a `HEAD` request and returns the value of `header`.
+fn head_header(client: &Client, url: &str, header: &str) -> Result<String> {
+    let res = client
+        .head(url)
+        .timeout(CHECK_TIMEOUT)
+        .send()
+        .and_then(|res| res.error_for_status())
+        .map_err(Error::network(url))?;
+    let value = res
+        .headers()
+        .get(header)
+        .ok_or_else(|| Error::schema(url, format!("missing {header} header")))?;
+    value
+        .to_str()
+        .map(str::to_string)
+        .map_err(|_| Error::schema(url, format!("invalid {header} header")))
+}
+
 #[inline]
-fn last_modified_header(client: &Client, url: &str) -> DateTime<Utc> {
-    Utc.from_local_datetime(
-        &NaiveDateTime::parse_from_str(
-            client
-                .head(url)
-                .send()
-                .unwrap()
-                .headers()
-                .get("Last-Modified")
-                .unwrap()
-                .to_str()
-                .unwrap(),
-            "%a, %d %b %Y %H:%M:%S GMT",
-        )
-        .unwrap(),
-    )
-    .unwrap()
+fn last_modified_header(client: &Client, url: &str) -> Result<DateTime<Utc>> {
+    let value = head_header(client, url, "Last-Modified")?;
+    NaiveDateTime::parse_from_str(&value, "%a, %d %b %Y %H:%M:%S GMT")
+        .map(|i| Utc.from_utc_datetime(&i))
+        .map_err(|_| Error::schema(url, format!("invalid Last-Modified header \"{value}\"")))
 }
 
 // TODO: make this only check once every few hours or day
 #[inline]
-fn latest_gwas_date(client: &Client) -> NaiveDate {
-    client
-        .head("https://www.ebi.ac.uk/gwas/api/search/downloads/alternative")
-        .send()
-        .unwrap()
-        .headers()
-        .get("Content-Disposition")
-        .unwrap()
-        .to_str()
-        .unwrap()
+fn latest_gwas_date(client: &Client) -> Result<NaiveDate> {
+    let value = head_header(client, GWAS_URL, "Content-Disposition")?;
+    // e.g. `attachment; filename=gwas-catalog-v1.0.2-associations_e111_r2024-05-20.tsv`
+    value
         .split('=')
-        .last()
-        .unwrap()
-        .split('_')
-        .last()
-        .unwrap()[1..]
-        .split('.')
-        .next()
-        .unwrap()
-        .parse::<NaiveDate>()
-        .unwrap()
+        .next_back()
+        .and_then(|i| i.split('_').next_back())
+        .and_then(|i| i.get(1..))
+        .and_then(|i| i.split('.').next())
+        .and_then(|i| i.parse::<NaiveDate>().ok())
+        .ok_or_else(|| {
+            Error::schema(
+                GWAS_URL,
+                format!("no release date in Content-Disposition header \"{value}\""),
+            )
+        })
 }
 
 #[inline]
-fn latest_efo_date(client: &Client) -> DateTime<Utc> {
-    last_modified_header(client, "https://www.ebi.ac.uk/efo/efo.owl")
+fn latest_efo_date(client: &Client) -> Result<DateTime<Utc>> {
+    last_modified_header(client, EFO_URL)
+}
+
+/// Downloads `url` as text.
+fn download(client: &Client, url: &str) -> Result<String> {
+    client
+        .get(url)
+        .send()
+        .and_then(|res| res.error_for_status())
+        .and_then(|res| res.text())
+        .map_err(Error::network(url))
 }
 
 struct WriteFile<'a> {
@@ -88,20 +113,28 @@ impl<'a> WriteFile<'a> {
         Self { path, tmp }
     }
 
-    fn write_archive<T>(self, data: &T)
+    fn write_archive<T>(self, data: &T) -> Result<()>
     where
         T: rkyv::Serialize<AllocSerializer<256>>,
     {
-        let mut tmp = File::create(&self.tmp).unwrap();
-        let bytes = rkyv::to_bytes::<T, 256>(data).unwrap();
-        tmp.write_all(&bytes).unwrap();
-        std::fs::rename(&self.tmp, &self.path).unwrap();
+        let bytes = rkyv::to_bytes::<T, 256>(data).map_err(|e| {
+            Error::cache(self.path)(std::io::Error::other(format!("failed to serialize: {e}")))
+        })?;
+        let mut file = Vec::with_capacity(ARCHIVE_HEADER_LEN + bytes.len());
+        file.extend_from_slice(&archive_header());
+        file.extend_from_slice(&bytes);
+        self.write_bytes(&file)
     }
 
-    fn write_str(self, data: &str) {
-        let mut tmp = File::create(&self.tmp).unwrap();
-        tmp.write_all(data.as_bytes()).unwrap();
-        std::fs::rename(&self.tmp, &self.path).unwrap();
+    fn write_str(self, data: &str) -> Result<()> {
+        self.write_bytes(data.as_bytes())
+    }
+
+    fn write_bytes(self, data: &[u8]) -> Result<()> {
+        File::create(&self.tmp)
+            .and_then(|mut tmp| tmp.write_all(data))
+            .map_err(Error::cache(&self.tmp))?;
+        std::fs::rename(&self.tmp, self.path).map_err(Error::cache(self.path))
     }
 }
 
@@ -110,221 +143,592 @@ impl Drop for WriteFile<'_> {
         match std::fs::metadata(&self.tmp) {
             Ok(_) => {
                 if let Err(e) = std::fs::remove_file(&self.tmp) {
-                    panic!("Failed to remove temporary file: {}", e);
+                    log::warn!("Failed to remove temporary file: {}", e);
                 }
             },
             Err(e) => match e.kind() {
                 std::io::ErrorKind::NotFound => {},
-                _ => panic!("Failed to read temporary file metadata: {}", e),
+                _ => log::warn!("Failed to read temporary file metadata: {}", e),
             },
         }
     }
 }
 
-fn write_gwas_file(client: &Client, dir: &Path, local: bool) {
+fn write_gwas_file(client: &Client, dir: &Path, local: bool) -> Result<()> {
     let tsv = associations_tsv_path(dir);
-    let processed = associations_path(dir);
     let file = if local {
-        println!("Loading local GWAS file...");
-        std::fs::read_to_string(tsv).unwrap()
+        eprintln!("Loading local GWAS file...");
+        std::fs::read_to_string(&tsv).map_err(Error::cache(&tsv))?
     } else {
-        println!("Downloading new GWAS file...");
-        let file = client
-            .get("https://www.ebi.ac.uk/gwas/api/search/downloads/alternative")
-            .send()
-            .unwrap()
-            .text()
-            .unwrap();
-        WriteFile::new(&tsv).write_str(&file);
+        eprintln!("Downloading new GWAS file...");
+        let file = download(client, GWAS_URL)?;
+        WriteFile::new(&tsv).write_str(&file)?;
         file
     };
+    process_gwas_file(dir, &tsv.display().to_string(), &file)
+}
+
+/// Processes the text of a GWAS Catalog associations TSV into the archive,
+/// `name` identifying it in errors.
+fn process_gwas_file(dir: &Path, name: &str, file: &str) -> Result<()> {
+    write_gwas_archives(dir, &parse_gwas_file(name, file)?)
+}
 
-    println!("Processing GWAS file...");
-    let headers = file.lines().next().unwrap().split('\t').collect::<Vec<_>>();
-    let disease = get_header_position(&headers, "MAPPED_TRAIT_URI");
-    let p_value = get_header_position(&headers, "P-VALUE");
-    let mapped_gene = get_header_position(&headers, "MAPPED_GENE");
-    let accession_id = get_header_position(&headers, "STUDY ACCESSION");
-    let link = headers.iter().position(|&header| header == "LINK").unwrap();
+fn write_gwas_archives(dir: &Path, associations: &Vec<Association>) -> Result<()> {
+    WriteFile::new(&associations_path(dir)).write_archive(associations)?;
+    WriteFile::new(&index_path(dir)).write_archive(&Index::build(associations))?;
+
+    eprintln!("Processed GWAS file");
+    Ok(())
+}
+
+/// Parses the text of a GWAS Catalog associations TSV, `name` identifying it
+/// in errors.
+fn parse_gwas_file(name: &str, file: &str) -> Result<Vec<Association>> {
+    eprintln!("Processing GWAS file...");
+    let headers = file
+        .lines()
+        .next()
+        .ok_or_else(|| Error::schema(name, "file is empty"))?
+        .split('\t')
+        .collect::<Vec<_>>();
+    let header = |header| get_header_position(name, &headers, header);
+    let disease = header("MAPPED_TRAIT_URI")?;
+    let p_value = header("P-VALUE")?;
+    let mapped_gene = header("MAPPED_GENE")?;
+    let reported_gene = header("REPORTED GENE(S)")?;
+    let upstream_gene_id = header("UPSTREAM_GENE_ID")?;
+    let downstream_gene_id = header("DOWNSTREAM_GENE_ID")?;
+    let upstream_gene_distance = header("UPSTREAM_GENE_DISTANCE")?;
+    let downstream_gene_distance = header("DOWNSTREAM_GENE_DISTANCE")?;
+    let accession_id = header("STUDY ACCESSION")?;
+    let link = header("LINK")?;
+    let snps = header("SNPS")?;
+    let strongest_snp_risk_allele = header("STRONGEST SNP-RISK ALLELE")?;
+    let snp_id_current = header("SNP_ID_CURRENT")?;
+    let chr_id = header("CHR_ID")?;
+    let chr_pos = header("CHR_POS")?;
+    let effect = header("OR or BETA")?;
+    let ci = header("95% CI (TEXT)")?;
+    let risk_allele_frequency = header("RISK ALLELE FREQUENCY")?;
+    let columns = headers.len();
     let mut associations = file
         .lines()
+        .enumerate()
         .skip(1)
+        .filter(|(_, line)| !line.is_empty())
         .par_bridge()
-        .map(|line| line.split('\t').collect::<Vec<_>>())
-        .filter_map(|record| {
+        .map(|(i, line)| {
+            let record = line.split('\t').collect::<Vec<_>>();
+            // reported 1-based, counting the header
+            let line = i + 1;
+            let invalid = |column: &str, value: &str| {
+                Error::parse(name, format!("invalid {column} \"{value}\" on line {line}"))
+            };
+            if record.len() < columns {
+                return Err(Error::parse(
+                    name,
+                    format!(
+                        "expected {columns} columns but found {} on line {line}",
+                        record.len()
+                    ),
+                ));
+            }
+            // terms of ontologies we don't model are skipped, but a malformed
+            // EFO term is still an error
             let mut traits = record[disease]
                 .split(',')
-                .map(|disease| disease.split('/').last().unwrap())
-                .filter_map(|disease| {
-                    if disease.starts_with("EFO_") {
-                        Some(disease.split('_').last().unwrap().parse().unwrap())
-                    } else {
-                        None
-                    }
+                .map(|disease| disease.trim())
+                .filter_map(|disease| match TermId::parse(disease) {
+                    Some(id) => Some(Ok(id)),
+                    None if disease.contains("EFO_") => {
+                        Some(Err(invalid("MAPPED_TRAIT_URI", disease)))
+                    },
+                    None => None,
                 })
-                .collect::<Vec<_>>();
+                .collect::<Result<Vec<_>>>()?;
             traits.sort();
-            // does the ,/-/;/x indicate a gene combination, or two separate genes?
-            if record[mapped_gene].trim().is_empty() {
-                return None;
-            }
-            let mut mapped_gene = vec![record[mapped_gene].trim().to_uppercase()];
-            mapped_gene.sort();
-            Some(Association {
+            // rows without a mapped gene are kept so variant lookups still find them
+            let (mapped_gene, flanking_gene, gene_context) = parse_mapped_gene(record[mapped_gene]);
+            let (ci_lower, ci_upper, effect_unit) = parse_ci(record[ci]);
+            Ok(Association {
                 traits,
-                p_value: record[p_value].parse().unwrap(),
+                p_value: parse_p_value(record[p_value])
+                    .ok_or_else(|| invalid("P-VALUE", record[p_value]))?,
                 mapped_gene,
-                accession_id: record[accession_id][4..].parse().unwrap(),
-                pubmed: record[link].split('/').last().unwrap().parse().unwrap(),
+                flanking_gene,
+                gene_context,
+                upstream_gene_id: non_empty(record[upstream_gene_id]),
+                downstream_gene_id: non_empty(record[downstream_gene_id]),
+                upstream_gene_distance: record[upstream_gene_distance].trim().parse().ok(),
+                downstream_gene_distance: record[downstream_gene_distance].trim().parse().ok(),
+                reported_gene: parse_reported_gene(record[reported_gene]),
+                accession_id: record[accession_id]
+                    .trim()
+                    .strip_prefix("GCST")
+                    .and_then(|i| i.parse().ok())
+                    .ok_or_else(|| invalid("STUDY ACCESSION", record[accession_id]))?,
+                pubmed: record[link]
+                    .trim()
+                    .split('/')
+                    .next_back()
+                    .and_then(|i| i.parse().ok())
+                    .ok_or_else(|| invalid("LINK", record[link]))?,
+                snps: split_multi(record[snps])
+                    .map(|i| i.trim().to_lowercase())
+                    .filter(|i| !i.is_empty())
+                    .collect(),
+                strongest_snp_risk_allele: record[strongest_snp_risk_allele].trim().to_string(),
+                snp_id_current: record[snp_id_current].trim().parse().ok(),
+                locations: split_multi(record[chr_id])
+                    .zip(split_multi(record[chr_pos]))
+                    .filter_map(|(chr, pos)| Location::parse(chr, pos))
+                    .collect(),
+                effect: record[effect].trim().parse().ok(),
+                ci_lower,
+                ci_upper,
+                effect_unit,
+                risk_allele_frequency: record[risk_allele_frequency].trim().parse().ok(),
             })
         })
-        .collect::<Vec<_>>();
+        .collect::<Result<Vec<_>>>()?;
     associations.sort();
     associations.dedup();
-    WriteFile::new(&processed).write_archive(&associations);
-
-    println!("Processed GWAS file");
+    Ok(associations)
 }
 
-fn write_efo_file(client: &Client, dir: &Path, local: bool) {
+fn write_efo_file(client: &Client, dir: &Path, local: bool) -> Result<()> {
     let owl = efo_owl_path(dir);
-    let processed = efo_path(dir);
     let file = if local {
-        println!("Loading local EFO file...");
-        std::fs::read_to_string(owl).unwrap()
+        eprintln!("Loading local EFO file...");
+        std::fs::read_to_string(&owl).map_err(Error::cache(&owl))?
     } else {
-        println!("Downloading new EFO file...");
-        let file = client
-            .get("https://www.ebi.ac.uk/efo/efo.owl")
-            .send()
-            .unwrap()
-            .text()
-            .unwrap();
-        WriteFile::new(&owl).write_str(&file);
+        eprintln!("Downloading new EFO file...");
+        let file = download(client, EFO_URL)?;
+        WriteFile::new(&owl).write_str(&file)?;
         file
     };
+    process_efo_file(dir, &owl.display().to_string(), &file)
+}
 
-    println!("Processing EFO file...");
-    let efo = roxmltree::Document::parse(&file).unwrap();
+/// Processes the text of an EFO OWL file into the archive, `name` identifying
+/// it in errors.
+fn process_efo_file(dir: &Path, name: &str, file: &str) -> Result<()> {
+    write_efo_archive(dir, &parse_efo_file(name, file)?)
+}
+
+fn write_efo_archive(dir: &Path, efos: &Vec<Efo>) -> Result<()> {
+    WriteFile::new(&efo_path(dir)).write_archive(efos)?;
+
+    eprintln!("Processed EFO file");
+    Ok(())
+}
+
+/// Parses the text of an EFO OWL file, `name` identifying it in errors.
+fn parse_efo_file(name: &str, file: &str) -> Result<Vec<Efo>> {
+    eprintln!("Processing EFO file...");
+    let efo = roxmltree::Document::parse(file).map_err(|e| Error::parse(name, e.to_string()))?;
     let mut efos = efo
         .descendants()
         .par_bridge()
         .filter_map(|node| {
-            if node.has_tag_name((OWL_NS, "Class")) {
-                if let Some(id) = node.attribute((RDF_NS, "about")) {
-                    if let Some(label) = node
-                        .children()
-                        .find(|node| node.has_tag_name((RDFS_NS, "label")))
-                    {
-                        let id = id.split('/').last().unwrap();
-                        if id.starts_with("EFO_") {
-                            let id = id.split('_').last().unwrap().parse().unwrap();
-                            let label = label.text().unwrap().trim().to_uppercase();
-                            let parent = node
-                                .children()
-                                .find(|node| node.has_tag_name((RDFS_NS, "subClassOf")))
-                                .map(|node| {
-                                    let id = node
-                                        .attribute((RDF_NS, "resource"))
-                                        .unwrap()
-                                        .split('/')
-                                        .last()
-                                        .unwrap();
-                                    if id.starts_with("EFO_") {
-                                        id.split('_').last().unwrap().parse().unwrap()
-                                    } else {
-                                        0
-                                    }
-                                });
-                            let synonyms = node
-                                .children()
-                                .filter(|node| {
-                                    node.has_tag_name((OBO_IN_OWL_NS, "hasExactSynonym"))
-                                        && node.is_text()
-                                })
-                                .map(|node| node.text().unwrap().trim().to_uppercase())
-                                .collect::<HashSet<_>>();
-                            return Some((
-                                id,
-                                Efo {
-                                    id,
-                                    label,
-                                    parent,
-                                    children: HashSet::new(),
-                                    synonyms,
-                                },
-                            ));
-                        }
-                    }
+            if !node.has_tag_name((OWL_NS, "Class")) {
+                return None;
+            }
+            let id = TermId::parse(node.attribute((RDF_NS, "about"))?)?;
+            let label = node
+                .children()
+                .find(|node| node.has_tag_name((RDFS_NS, "label")))?
+                .text()?
+                .trim()
+                .to_uppercase();
+            let mut parents = Vec::new();
+            let mut relations = Vec::new();
+            for superclass in node
+                .children()
+                .filter(|node| node.has_tag_name((RDFS_NS, "subClassOf")))
+            {
+                if let Some(resource) = superclass.attribute((RDF_NS, "resource")) {
+                    parents.extend(TermId::parse(resource));
+                } else if let Some(relation) = superclass
+                    .children()
+                    .find(|node| node.has_tag_name((OWL_NS, "Restriction")))
+                    .and_then(parse_restriction)
+                {
+                    relations.push(relation);
                 }
             }
-            None
+            parents.sort();
+            parents.dedup();
+            let synonyms = node
+                .children()
+                .filter(|node| node.has_tag_name((OBO_IN_OWL_NS, "hasExactSynonym")))
+                .filter_map(|node| node.text())
+                .map(|synonym| synonym.trim().to_uppercase())
+                .collect::<HashSet<_>>();
+            Some((
+                id,
+                Efo {
+                    id,
+                    label,
+                    parents,
+                    relations,
+                    children: HashSet::new(),
+                    synonyms,
+                },
+            ))
         })
         .collect::<HashMap<_, _>>();
-    for (id, efo) in efos.clone().into_iter() {
-        if let Some(subclass) = efo.parent {
-            if let Some(parent) = efos.get_mut(&subclass) {
-                parent.children.insert(id);
-            }
+    let edges = efos
+        .values()
+        .flat_map(|efo| efo.parents.iter().map(|parent| (*parent, efo.id)))
+        .collect::<Vec<_>>();
+    for (parent, child) in edges {
+        if let Some(parent) = efos.get_mut(&parent) {
+            parent.children.insert(child);
+        }
+    }
+    Ok(efos.into_values().collect())
+}
+
+/// Processes the text of an HGNC complete set TSV into the archive, `name`
+/// identifying it in errors.
+fn process_hgnc_file(dir: &Path, name: &str, file: &str) -> Result<()> {
+    write_hgnc_archive(dir, &parse_hgnc_file(name, file)?)
+}
+
+fn write_hgnc_archive(dir: &Path, hgnc: &Hgnc) -> Result<()> {
+    WriteFile::new(&hgnc_path(dir)).write_archive(hgnc)?;
+
+    eprintln!("Processed {} HGNC genes", hgnc.approved.len());
+    Ok(())
+}
+
+/// Parses the text of an HGNC complete set TSV, `name` identifying it in
+/// errors. Multiple aliases, previous symbols and IDs are separated by `|`.
+fn parse_hgnc_file(name: &str, file: &str) -> Result<Hgnc> {
+    eprintln!("Processing HGNC file...");
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(b'\t')
+        .from_reader(file.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| Error::parse(name, format!("failed to read the header: {e}")))?
+        .clone();
+    let headers = headers.iter().collect::<Vec<_>>();
+    let header = |header| get_header_position(name, &headers, header);
+    let hgnc_id = header("hgnc_id")?;
+    let symbol = header("symbol")?;
+    let alias_symbol = header("alias_symbol")?;
+    let prev_symbol = header("prev_symbol")?;
+    let ensembl_gene_id = header("ensembl_gene_id")?;
+    let entrez_id = header("entrez_id")?;
+    let mut hgnc = Hgnc::default();
+    for (i, record) in reader.records().enumerate() {
+        // reported 1-based, counting the header
+        let record = record
+            .map_err(|e| Error::parse(name, format!("invalid record on line {}: {e}", i + 2)))?;
+        let field = |column: usize| {
+            record
+                .get(column)
+                .unwrap_or_default()
+                .split('|')
+                .filter_map(non_empty)
+                .map(|i| i.to_uppercase())
+        };
+        let symbol = match field(symbol).next() {
+            Some(symbol) => symbol,
+            None => continue,
+        };
+        for id in field(hgnc_id)
+            .chain(field(ensembl_gene_id))
+            .chain(field(entrez_id))
+        {
+            hgnc.ids.insert(id, symbol.clone());
+        }
+        for previous in field(prev_symbol) {
+            hgnc.previous
+                .entry(previous)
+                .or_default()
+                .push(symbol.clone());
+        }
+        for alias in field(alias_symbol) {
+            hgnc.aliases.entry(alias).or_default().push(symbol.clone());
+        }
+        hgnc.approved.insert(symbol);
+    }
+    for symbols in hgnc.previous.values_mut().chain(hgnc.aliases.values_mut()) {
+        symbols.sort();
+        symbols.dedup();
+    }
+    Ok(hgnc)
+}
+
+/// Parses an `owl:Restriction` such as `part_of some EFO_0000001` into a
+/// typed relation, skipping properties and fillers we don't track.
+fn parse_restriction(node: roxmltree::Node) -> Option<Relation> {
+    let property = node
+        .children()
+        .find(|node| node.has_tag_name((OWL_NS, "onProperty")))?
+        .attribute((RDF_NS, "resource"))?
+        .split('/')
+        .next_back()?;
+    let kind = RelationKind::from_property(property)?;
+    let target = node
+        .children()
+        .find(|node| node.has_tag_name((OWL_NS, "someValuesFrom")))?
+        .attribute((RDF_NS, "resource"))?;
+    Some(Relation {
+        kind,
+        target: TermId::parse(target)?,
+    })
+}
+
+/// Reprocesses the cached `associations.tsv` and `efo.owl` without checking
+/// for updates, so it works offline.
+pub fn reprocess(dir: &Path) -> Result<()> {
+    let client = client()?;
+    write_gwas_file(&client, dir, true)?;
+    write_efo_file(&client, dir, true)?;
+    let tsv = hgnc_tsv_path(dir);
+    if tsv.exists() {
+        let file = std::fs::read_to_string(&tsv).map_err(Error::cache(&tsv))?;
+        process_hgnc_file(dir, &tsv.display().to_string(), &file)?;
+    }
+    Ok(())
+}
+
+/// Where `import` reads a GWAS Catalog, EFO or HGNC release from, optionally
+/// gzipped or zipped.
+pub enum ImportSource<'a> {
+    Url(&'a str),
+    Path(&'a Path),
+}
+
+impl<'a> ImportSource<'a> {
+    /// Treats `http://` and `https://` sources as URLs and anything else as a
+    /// path.
+    pub fn new(source: &'a str) -> Self {
+        if source.starts_with("http://") || source.starts_with("https://") {
+            Self::Url(source)
+        } else {
+            Self::Path(Path::new(source))
+        }
+    }
+
+    #[inline]
+    pub fn is_url(&self) -> bool {
+        matches!(self, Self::Url(_))
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Url(url) => url.to_string(),
+            Self::Path(path) => path.display().to_string(),
+        }
+    }
+
+    fn read(&self, client: &Client) -> Result<String> {
+        let bytes = match self {
+            Self::Url(url) => {
+                eprintln!("Downloading {url}...");
+                client
+                    .get(*url)
+                    .send()
+                    .and_then(|res| res.error_for_status())
+                    .and_then(|res| res.bytes())
+                    .map_err(Error::network(url))?
+                    .to_vec()
+            },
+            Self::Path(path) => {
+                eprintln!("Loading {}...", path.display());
+                std::fs::read(path).map_err(Error::cache(path))?
+            },
+        };
+        decompress(&self.name(), bytes)
+    }
+}
+
+/// Decompresses gzip and zip files by their magic bytes, anything else is
+/// read as plain text. A zip archive must contain exactly one file.
+fn decompress(name: &str, bytes: Vec<u8>) -> Result<String> {
+    let mut text = String::new();
+    if bytes.starts_with(&[0x1f, 0x8b]) {
+        GzDecoder::new(&bytes[..])
+            .read_to_string(&mut text)
+            .map_err(|e| Error::parse(name, format!("invalid gzip file: {e}")))?;
+    } else if bytes.starts_with(b"PK\x03\x04") {
+        let mut archive = zip::ZipArchive::new(std::io::Cursor::new(bytes))
+            .map_err(|e| Error::parse(name, format!("invalid zip file: {e}")))?;
+        let files = archive
+            .file_names()
+            .filter(|i| !i.ends_with('/') && !i.starts_with("__MACOSX/"))
+            .map(String::from)
+            .collect::<Vec<_>>();
+        let [file] = &files[..] else {
+            return Err(Error::schema(
+                name,
+                format!(
+                    "expected a single file in the zip archive, found {}",
+                    files.len()
+                ),
+            ));
+        };
+        archive
+            .by_name(file)
+            .map_err(|e| Error::parse(name, format!("invalid zip file: {e}")))?
+            .read_to_string(&mut text)
+            .map_err(|e| Error::parse(name, format!("failed to extract {file}: {e}")))?;
+    } else {
+        text = String::from_utf8(bytes)
+            .map_err(|_| Error::parse(name, "expected plain text, gzip or zip"))?;
+    }
+    Ok(text)
+}
+
+/// Processes a GWAS Catalog associations TSV, EFO OWL release and/or HGNC
+/// complete set TSV into the archives, replacing the cached files. Imported
+/// GWAS and EFO data is then pinned, so update checks leave it alone until
+/// `update --force`. HGNC data is never downloaded by updates, so it isn't.
+pub fn import(
+    dir: &Path,
+    gwas: Option<ImportSource>,
+    efo: Option<ImportSource>,
+    hgnc: Option<ImportSource>,
+) -> Result<()> {
+    fn read<T>(
+        client: &Client,
+        source: Option<ImportSource>,
+        parse: fn(&str, &str) -> Result<T>,
+    ) -> Result<Option<(T, String)>> {
+        source
+            .map(|source| {
+                let file = source.read(client)?;
+                Ok((parse(&source.name(), &file)?, file))
+            })
+            .transpose()
+    }
+
+    let client = client()?;
+    // every file is read and parsed before anything is written, so a bad one
+    // leaves the old data intact
+    let hgnc = read(&client, hgnc, parse_hgnc_file)?;
+    let gwas = read(&client, gwas, parse_gwas_file)?;
+    let efo = read(&client, efo, parse_efo_file)?;
+    if let Some((hgnc, file)) = hgnc {
+        write_hgnc_archive(dir, &hgnc)?;
+        WriteFile::new(&hgnc_tsv_path(dir)).write_str(&file)?;
+    }
+    if gwas.is_none() && efo.is_none() {
+        return Ok(());
+    }
+    if let Some((associations, file)) = gwas {
+        write_gwas_archives(dir, &associations)?;
+        WriteFile::new(&associations_tsv_path(dir)).write_str(&file)?;
+    }
+    if let Some((efos, file)) = efo {
+        write_efo_archive(dir, &efos)?;
+        WriteFile::new(&efo_owl_path(dir)).write_str(&file)?;
+    }
+    let pinned = pinned_path(dir);
+    std::fs::write(&pinned, "").map_err(Error::cache(&pinned))
+}
+
+/// Checks that the processed archives exist without touching the network,
+/// for offline mode.
+pub fn check_archives(dir: &Path) -> Result<()> {
+    for path in [associations_path(dir), efo_path(dir)] {
+        if !path.exists() {
+            return Err(Error::Offline { path });
         }
     }
-    WriteFile::new(&processed).write_archive(&efos.into_values().collect::<Vec<_>>());
+    Ok(())
+}
 
-    println!("Processed EFO file");
+fn client() -> Result<Client> {
+    ClientBuilder::new()
+        .connect_timeout(CONNECT_TIMEOUT)
+        .timeout(None)
+        .build()
+        .map_err(Error::network("the HTTP client"))
 }
 
-pub fn check_for_updates(dir: &Path, local: bool, force: u8) {
-    let client = ClientBuilder::new().timeout(None).build().unwrap();
+/// Modification time of the file at `path`, `None` if it doesn't exist.
+fn modified(path: &Path) -> Result<Option<DateTime<Utc>>> {
+    match std::fs::metadata(path).and_then(|i| i.modified()) {
+        Ok(modified) => Ok(Some(modified.into())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::cache(path)(e)),
+    }
+}
+
+pub fn check_for_updates(dir: &Path, local: bool, force: u8) -> Result<()> {
+    let pinned = pinned_path(dir);
+    if pinned.exists() {
+        if local {
+            return reprocess(dir);
+        }
+        if force == 0 {
+            return Ok(());
+        }
+    }
+    // a pinned release may be newer than the archives, so always redownload
+    let force = if pinned.exists() { 2 } else { force };
+    let client = client()?;
     let metadata_path = metadata_path(dir);
     match std::fs::read(&metadata_path) {
         Ok(bytes) => {
-            let metadata: Metadata = unsafe { rkyv::from_bytes_unchecked(&bytes).unwrap() };
-            let now = Utc::now();
-            if now - metadata.last_updated < chrono::Duration::days(1) && force == 0 {
-                return;
+            // an invalid metadata file only means we check for updates again
+            if let Some(metadata) = check_archive::<Metadata>(&aligned(&bytes)) {
+                let last_updated: DateTime<Utc> = metadata
+                    .last_updated
+                    .deserialize(&mut rkyv::Infallible)
+                    .expect("deserializing is infallible");
+                if Utc::now() - last_updated < chrono::Duration::days(1) && force == 0 {
+                    return Ok(());
+                }
             }
         },
         Err(e) => {
             if e.kind() != std::io::ErrorKind::NotFound {
-                panic!("Failed to read metadata file: {}", e);
+                return Err(Error::cache(&metadata_path)(e));
             }
         },
     }
 
-    let associations_path = associations_path(dir);
-    if let Ok(metadata) = std::fs::metadata(associations_path) {
-        let latest = latest_gwas_date(&client);
-        if DateTime::<Utc>::from(metadata.modified().unwrap())
-            .naive_utc()
-            .date()
-            < latest
-            || force == 2
-        {
-            write_gwas_file(&client, dir, local);
+    if let Some(modified) = modified(&associations_path(dir))? {
+        let latest = latest_gwas_date(&client)?;
+        if modified.naive_utc().date() < latest || force == 2 {
+            write_gwas_file(&client, dir, local)?;
         }
     } else {
-        write_gwas_file(&client, dir, false);
+        write_gwas_file(&client, dir, false)?;
     }
-    let efo_path = efo_path(dir);
-    if let Ok(metadata) = std::fs::metadata(efo_path) {
-        let latest = latest_efo_date(&client);
-        if DateTime::<Utc>::from(metadata.modified().unwrap()) < latest || force == 2 {
-            write_efo_file(&client, dir, local);
+    if let Some(modified) = modified(&efo_path(dir))? {
+        let latest = latest_efo_date(&client)?;
+        if modified < latest || force == 2 {
+            write_efo_file(&client, dir, local)?;
         }
     } else {
-        write_efo_file(&client, dir, false);
+        write_efo_file(&client, dir, false)?;
     }
 
-    let bytes = rkyv::to_bytes::<_, 0>(&Metadata {
+    WriteFile::new(&metadata_path).write_archive(&Metadata {
         last_updated: Utc::now(),
-    })
-    .unwrap();
-    std::fs::write(metadata_path, bytes).unwrap();
+    })?;
+    if pinned.exists() {
+        eprintln!("Unpinned imported data");
+        std::fs::remove_file(&pinned).map_err(Error::cache(&pinned))?;
+    }
+    Ok(())
 }
 
+/// `search-gwas` in the platform's data directory, falling back to the
+/// working directory, or a relative path if even that is unavailable.
 pub fn get_data_dir() -> PathBuf {
     dirs::data_dir()
-        .unwrap_or_else(|| current_dir().unwrap())
+        .or_else(|| current_dir().ok())
+        .unwrap_or_default()
         .join("search-gwas")
 }
 
@@ -341,6 +745,10 @@ pub fn get_az_dir() -> PathBuf {
     get_global_dir().join("az470k-proteomics")
 }
 
+pub fn az_associations_path(dir: &Path) -> PathBuf {
+    dir.join("az_associations.rkyv")
+}
+
 pub fn associations_path(dir: &Path) -> PathBuf {
     dir.join("associations.rkyv")
 }
@@ -349,6 +757,10 @@ pub fn associations_tsv_path(dir: &Path) -> PathBuf {
     dir.join("associations.tsv")
 }
 
+pub fn index_path(dir: &Path) -> PathBuf {
+    dir.join("index.rkyv")
+}
+
 pub fn efo_path(dir: &Path) -> PathBuf {
     dir.join("efo.rkyv")
 }
@@ -357,23 +769,454 @@ pub fn efo_owl_path(dir: &Path) -> PathBuf {
     dir.join("efo.owl")
 }
 
+pub fn config_path(dir: &Path) -> PathBuf {
+    dir.join("config.toml")
+}
+
 pub fn metadata_path(dir: &Path) -> PathBuf {
     dir.join("metadata.rkyv")
 }
 
+pub fn hgnc_path(dir: &Path) -> PathBuf {
+    dir.join("hgnc.rkyv")
+}
+
+pub fn hgnc_tsv_path(dir: &Path) -> PathBuf {
+    dir.join("hgnc.tsv")
+}
+
+/// Gene entries of `interactive`, oldest first.
+pub fn history_path(dir: &Path) -> PathBuf {
+    dir.join("history")
+}
+
+/// Marks data from `import` that update checks should leave alone.
+pub fn pinned_path(dir: &Path) -> PathBuf {
+    dir.join("pinned")
+}
+
+/// Splits `MAPPED_GENE` into the genes the variant lies in, the genes flanking
+/// it and how it relates to them. `,` and `;` separate genes the variant lies
+/// in, ` - ` the upstream and downstream genes of an intergenic variant and
+/// ` x ` the variants of an interaction. Gene symbols can contain `-`, so only
+/// the spaced form separates.
+fn parse_mapped_gene(value: &str) -> (Vec<String>, Vec<String>, GeneContext) {
+    let mut mapped = Vec::new();
+    let mut flanking = Vec::<String>::new();
+    for part in value.split(" x ").flat_map(|i| i.split(';')) {
+        if part.contains(" - ") {
+            for gene in part.split(" - ").filter_map(non_empty) {
+                let gene = gene.to_uppercase();
+                if !flanking.contains(&gene) {
+                    flanking.push(gene);
+                }
+            }
+        } else {
+            mapped.extend(
+                part.split(',')
+                    .filter_map(non_empty)
+                    .map(|i| i.to_uppercase()),
+            );
+        }
+    }
+    mapped.sort();
+    mapped.dedup();
+    let context = if value.contains(" x ") {
+        GeneContext::Interaction
+    } else if mapped.is_empty() && !flanking.is_empty() {
+        GeneContext::Intergenic
+    } else {
+        GeneContext::Intragenic
+    };
+    (mapped, flanking, context)
+}
+
+/// Splits `REPORTED GENE(S)` on `,` and `;`, dropping the `NR` and
+/// `intergenic` placeholders.
+fn parse_reported_gene(value: &str) -> Vec<String> {
+    let mut genes = value
+        .split([',', ';'])
+        .filter_map(non_empty)
+        .map(|i| i.to_uppercase())
+        .filter(|i| !matches!(i.as_str(), "NR" | "NA" | "INTERGENIC"))
+        .collect::<Vec<_>>();
+    genes.sort();
+    genes.dedup();
+    genes
+}
+
+/// The trimmed value, `None` if it is empty.
+#[inline]
+fn non_empty(value: &str) -> Option<String> {
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Splits a multi-valued catalog column, such as `SNPS` or `CHR_POS`, on the
+/// `;`, `,` and ` x ` separators.
+fn split_multi(value: &str) -> impl Iterator<Item = &str> {
+    value.split([';', ',']).flat_map(|i| i.split(" x "))
+}
+
+/// Parses `95% CI (TEXT)`, e.g. `[1.05-1.2]` or `[0.01-0.03] unit decrease`,
+/// into its bounds and unit text. Unreported bounds (`[NR]`) are `None`.
+fn parse_ci(value: &str) -> (Option<f64>, Option<f64>, String) {
+    let value = value.trim();
+    let (bounds, unit) = match value.strip_prefix('[').and_then(|i| i.split_once(']')) {
+        Some((bounds, unit)) => (bounds, unit),
+        None => ("", value),
+    };
+    let unit = unit.trim().to_string();
+    // the separator is the first `-` that doesn't start a negative bound or
+    // exponent
+    let bytes = bounds.as_bytes();
+    let separator =
+        (1..bytes.len()).find(|&i| bytes[i] == b'-' && !matches!(bytes[i - 1], b'-' | b'e' | b'E'));
+    match separator {
+        Some(i) => (
+            bounds[..i].trim().parse().ok(),
+            bounds[i + 1..].trim().parse().ok(),
+            unit,
+        ),
+        None => (None, None, unit),
+    }
+}
+
+/// Parses a `P-VALUE`, taking the leading number of annotated values such as
+/// `2E-8 (adjusted)`.
+fn parse_p_value(value: &str) -> Option<f64> {
+    let value = value.trim();
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.split_whitespace().next()?.parse().ok())
+}
+
+#[inline]
+fn get_header_position(name: &str, headers: &[&str], header: &str) -> Result<usize> {
+    headers
+        .iter()
+        .position(|&h| h == header)
+        .ok_or_else(|| Error::schema(name, format!("missing the \"{header}\" column")))
+}
+
+#[inline]
+fn archive_header() -> [u8; ARCHIVE_HEADER_LEN] {
+    let mut header = [0; ARCHIVE_HEADER_LEN];
+    header[..8].copy_from_slice(&ARCHIVE_MAGIC);
+    header[8..12].copy_from_slice(&ARCHIVE_VERSION.to_le_bytes());
+    header
+}
+
+/// Checks the header of an archive written by [`WriteFile::write_archive`]
+/// and validates the archive after it. `None` if it is corrupt or was written
+/// by another version.
+fn check_archive<'a, T>(bytes: &'a [u8]) -> Option<&'a T::Archived>
+where
+    T: Archive,
+    T::Archived: CheckBytes<DefaultValidator<'a>>,
+{
+    let archive = bytes.strip_prefix(&archive_header()[..])?;
+    rkyv::check_archived_root::<T>(archive).ok()
+}
+
+/// Copies `bytes` into an aligned buffer, as [`check_archive`] requires.
+fn aligned(bytes: &[u8]) -> AlignedVec {
+    let mut aligned = AlignedVec::with_capacity(bytes.len());
+    aligned.extend_from_slice(bytes);
+    aligned
+}
+
+/// Reads an archive from the data directory, erroring if it is missing.
+fn read_archive(path: &Path) -> Result<AlignedVec> {
+    std::fs::read(path)
+        .map(|i| aligned(&i))
+        .map_err(|e| match e.kind() {
+            std::io::ErrorKind::NotFound => Error::corrupt_cache(path),
+            _ => Error::cache(path)(e),
+        })
+}
+
+/// Called when the archive at `path` is missing, corrupt or from another
+/// version, checking that its cached `source` exists to reprocess it from.
+fn reprocess_from(path: &Path, source: &Path) -> Result<()> {
+    if !source.exists() {
+        return Err(Error::corrupt_cache(path));
+    }
+    if path.exists() {
+        eprintln!(
+            "{} is corrupt or from another version, reprocessing {}...",
+            path.display(),
+            source.display()
+        );
+    } else {
+        eprintln!(
+            "{} is missing, processing {}...",
+            path.display(),
+            source.display()
+        );
+    }
+    Ok(())
+}
+
+pub fn load_associations(dir: &Path) -> Result<Vec<Association>> {
+    let mmap = map_associations(dir)?;
+    // validated by `map_associations`
+    Ok(unsafe { archived_associations(&mmap) }
+        .deserialize(&mut rkyv::Infallible)
+        .expect("deserializing is infallible"))
+}
+
+fn map_file(path: &Path) -> Result<Mmap> {
+    let file = File::open(path).map_err(|e| match e.kind() {
+        std::io::ErrorKind::NotFound => Error::corrupt_cache(path),
+        _ => Error::cache(path)(e),
+    })?;
+    unsafe { Mmap::map(&file) }.map_err(Error::cache(path))
+}
+
+/// Memory-maps and validates the associations archive, to be read in place
+/// with [`archived_associations`]. An invalid archive is reprocessed from the
+/// cached `associations.tsv`.
+pub fn map_associations(dir: &Path) -> Result<Mmap> {
+    let path = associations_path(dir);
+    let mmap = map_file(&path)?;
+    if check_archive::<Vec<Association>>(&mmap).is_some() {
+        return Ok(mmap);
+    }
+    drop(mmap);
+    let tsv = associations_tsv_path(dir);
+    reprocess_from(&path, &tsv)?;
+    let file = std::fs::read_to_string(&tsv).map_err(Error::cache(&tsv))?;
+    process_gwas_file(dir, &tsv.display().to_string(), &file)?;
+    let mmap = map_file(&path)?;
+    match check_archive::<Vec<Association>>(&mmap) {
+        Some(_) => Ok(mmap),
+        None => Err(Error::corrupt_cache(&path)),
+    }
+}
+
+/// Views the associations archive without deserializing it.
+///
+/// # Safety
+///
+/// `bytes` must be an archive validated by [`map_associations`], header
+/// included.
+#[inline]
+pub unsafe fn archived_associations(bytes: &[u8]) -> &ArchivedVec<ArchivedAssociation> {
+    rkyv::archived_root::<Vec<Association>>(&bytes[ARCHIVE_HEADER_LEN..])
+}
+
+/// Memory-maps and validates the index of `associations`, rebuilding it if it
+/// is missing, invalid or was built for other associations.
+pub fn map_index(dir: &Path, associations: &ArchivedVec<ArchivedAssociation>) -> Result<Mmap> {
+    let path = index_path(dir);
+    if path.exists() {
+        let mmap = map_file(&path)?;
+        if check_archive::<Index>(&mmap)
+            .is_some_and(|i| i.len.value() as usize == associations.len())
+        {
+            return Ok(mmap);
+        }
+    }
+    eprintln!("Indexing associations...");
+    WriteFile::new(&path).write_archive(&Index::build(associations.as_slice()))?;
+    let mmap = map_file(&path)?;
+    match check_archive::<Index>(&mmap) {
+        Some(_) => Ok(mmap),
+        None => Err(Error::corrupt_cache(&path)),
+    }
+}
+
+/// Views the index without deserializing it.
+///
+/// # Safety
+///
+/// `bytes` must be an archive validated by [`map_index`], header included.
 #[inline]
-fn get_header_position(headers: &[&str], header: &str) -> usize {
-    headers.iter().position(|&h| h == header).unwrap()
+pub unsafe fn archived_index(bytes: &[u8]) -> &ArchivedIndex {
+    rkyv::archived_root::<Index>(&bytes[ARCHIVE_HEADER_LEN..])
 }
 
-pub fn load_associations(dir: &Path) -> Vec<Association> {
-    let file = std::fs::read(associations_path(dir)).unwrap();
-    unsafe { rkyv::from_bytes_unchecked::<Vec<Association>>(&file).unwrap() }
+/// Loads and deserializes the archive at `path`, or if it is missing or
+/// invalid, calls `process` to process it from its cached `source` first.
+/// `None` if neither the archive nor its source exist.
+fn load_archive<T>(
+    path: &Path,
+    source: &Path,
+    process: impl FnOnce() -> Result<()>,
+) -> Result<Option<T>>
+where
+    T: Archive,
+    T::Archived: for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, rkyv::Infallible>,
+{
+    fn deserialize<T>(file: &AlignedVec) -> Option<T>
+    where
+        T: Archive,
+        T::Archived: for<'a> CheckBytes<DefaultValidator<'a>> + Deserialize<T, rkyv::Infallible>,
+    {
+        check_archive::<T>(file).map(|i| {
+            i.deserialize(&mut rkyv::Infallible)
+                .expect("deserializing is infallible")
+        })
+    }
+
+    if path.exists() {
+        if let Some(value) = deserialize(&read_archive(path)?) {
+            return Ok(Some(value));
+        }
+    } else if !source.exists() {
+        return Ok(None);
+    }
+    reprocess_from(path, source)?;
+    process()?;
+    match deserialize(&read_archive(path)?) {
+        Some(value) => Ok(Some(value)),
+        None => Err(Error::corrupt_cache(path)),
+    }
 }
 
-pub fn load_efo(dir: &Path) -> Vec<Efo> {
-    let file = std::fs::read(efo_path(dir)).unwrap();
-    unsafe { rkyv::from_bytes_unchecked::<Vec<Efo>>(&file).unwrap() }
+/// Loads the EFO archive, processing it from the cached `efo.owl` if it is
+/// missing or invalid.
+pub fn load_efo(dir: &Path) -> Result<Vec<Efo>> {
+    let path = efo_path(dir);
+    let owl = efo_owl_path(dir);
+    let efos = load_archive(&path, &owl, || {
+        let source = std::fs::read_to_string(&owl).map_err(Error::cache(&owl))?;
+        process_efo_file(dir, &owl.display().to_string(), &source)
+    })?;
+    efos.ok_or_else(|| Error::corrupt_cache(&path))
+}
+
+/// Loads the HGNC archive, `None` if no HGNC complete set has been imported.
+/// An invalid archive is reprocessed from the cached `hgnc.tsv`.
+pub fn load_hgnc(dir: &Path) -> Result<Option<Hgnc>> {
+    let tsv = hgnc_tsv_path(dir);
+    load_archive(&hgnc_path(dir), &tsv, || {
+        let source = std::fs::read_to_string(&tsv).map_err(Error::cache(&tsv))?;
+        process_hgnc_file(dir, &tsv.display().to_string(), &source)
+    })
+}
+
+/// Where `az-update` reads the AZ PheWAS CSVs from.
+pub enum AzSource<'a> {
+    /// Base URL that `<dataset>.csv.gz` is appended to.
+    Url(&'a str),
+    /// Directory containing `<dataset>.csv.gz`.
+    Path(&'a Path),
+}
+
+/// Checks that a gzipped AZ PheWAS CSV has every column `AzAssociation`
+/// needs.
+fn validate_az_headers(name: &str, data: impl std::io::Read) -> Result<()> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(true)
+        .from_reader(GzDecoder::new(data));
+    let headers = reader
+        .headers()
+        .map_err(|e| Error::parse(name, format!("failed to read the header: {e}")))?;
+    for column in AZ_COLUMNS {
+        if !headers.iter().any(|i| i == column) {
+            return Err(Error::schema(
+                name,
+                format!("missing the \"{column}\" column"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+pub fn update_az(dir: &Path, source: AzSource) -> Result<()> {
+    std::fs::create_dir_all(dir).map_err(Error::cache(dir))?;
+    let client = client()?;
+    // every dataset is validated before any is written, so a bad one leaves
+    // the previous datasets and archive in place
+    let mut datasets = Vec::with_capacity(AZ_DATASETS.len());
+    for dataset in AZ_DATASETS {
+        let name = format!("{dataset}.csv.gz");
+        let bytes = match source {
+            AzSource::Url(url) => {
+                let url = format!("{}/{}", url.trim_end_matches('/'), name);
+                eprintln!("Downloading {url}...");
+                let res = client.get(&url).send().map_err(Error::network(&url))?;
+                if res.status() == reqwest::StatusCode::NOT_FOUND {
+                    eprintln!("No {dataset} dataset found, skipping");
+                    None
+                } else {
+                    let bytes = res
+                        .error_for_status()
+                        .and_then(|res| res.bytes())
+                        .map_err(Error::network(&url))?;
+                    validate_az_headers(&url, &bytes[..])?;
+                    Some(bytes.into())
+                }
+            },
+            AzSource::Path(path) => {
+                let src = path.join(&name);
+                if src.exists() {
+                    eprintln!("Reading {}...", src.display());
+                    let bytes = std::fs::read(&src).map_err(Error::cache(&src))?;
+                    validate_az_headers(&src.display().to_string(), &bytes[..])?;
+                    Some(bytes)
+                } else {
+                    eprintln!("No {dataset} dataset found, skipping");
+                    None
+                }
+            },
+        };
+        datasets.push((dir.join(name), bytes));
+    }
+    if datasets.iter().all(|(_, bytes)| bytes.is_none()) {
+        return Err(Error::schema(
+            match source {
+                AzSource::Url(url) => url.to_string(),
+                AzSource::Path(path) => path.display().to_string(),
+            },
+            format!(
+                "no AZ PheWAS datasets found, expected at least one of {AZ_DATASETS:?} as .csv.gz"
+            ),
+        ));
+    }
+
+    // removed first so it is rebuilt from whatever was written if writing
+    // fails part way
+    remove_file(&az_associations_path(dir))?;
+    for (dest, bytes) in datasets {
+        match bytes {
+            Some(bytes) => WriteFile::new(&dest).write_bytes(&bytes)?,
+            // no longer part of the source
+            None => remove_file(&dest)?,
+        }
+    }
+    process_az(dir)
+}
+
+/// Removes the file at `path` if it exists.
+fn remove_file(path: &Path) -> Result<()> {
+    match std::fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(Error::cache(path)(e)),
+        _ => Ok(()),
+    }
+}
+
+fn process_az(dir: &Path) -> Result<()> {
+    eprintln!("Processing AZ PheWAS files...");
+    let associations = ParallelIterator::collect::<Vec<_>>(AzAssociations::new(dir));
+    WriteFile::new(&az_associations_path(dir)).write_archive(&associations)?;
+
+    eprintln!("Processed {} AZ PheWAS associations", associations.len());
+    Ok(())
+}
+
+/// Loads the AZ PheWAS archive, `None` if `az-update` hasn't been run. A
+/// missing or invalid archive is reprocessed from the downloaded CSVs.
+pub fn load_az_associations(dir: &Path) -> Result<Option<Vec<AzAssociation>>> {
+    let source = AZ_DATASETS
+        .iter()
+        .map(|i| dir.join(format!("{i}.csv.gz")))
+        .find(|i| i.exists())
+        .unwrap_or_else(|| dir.join("binary.csv.gz"));
+    load_archive(&az_associations_path(dir), &source, || process_az(dir))
 }
 
 pub struct AzAssociations {
@@ -383,8 +1226,8 @@ pub struct AzAssociations {
 }
 
 impl AzAssociations {
-    pub fn new() -> Self {
-        let file = std::fs::File::open(get_az_dir().join("binary.csv.gz"));
+    pub fn new(dir: &Path) -> Self {
+        let file = std::fs::File::open(dir.join("binary.csv.gz"));
         let binary = if let Ok(file) = file {
             let reader = csv::ReaderBuilder::new()
                 .has_headers(true)
@@ -393,7 +1236,7 @@ impl AzAssociations {
         } else {
             None
         };
-        let file = std::fs::File::open(get_az_dir().join("proteomics.csv.gz"));
+        let file = std::fs::File::open(dir.join("proteomics.csv.gz"));
         let proteomics = if let Ok(file) = file {
             let reader = csv::ReaderBuilder::new()
                 .has_headers(true)
@@ -402,7 +1245,7 @@ impl AzAssociations {
         } else {
             None
         };
-        let file = std::fs::File::open(get_az_dir().join("quantitative.csv.gz"));
+        let file = std::fs::File::open(dir.join("quantitative.csv.gz"));
         let quantitative = if let Ok(file) = file {
             let reader = csv::ReaderBuilder::new()
                 .has_headers(true)
@@ -423,19 +1266,17 @@ impl Iterator for AzAssociations {
     type Item = AzAssociation;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(iter) = &mut self.binary {
-            if let Some(assoc) = iter.next() {
-                return Some(assoc.unwrap());
-            }
-        }
-        if let Some(iter) = &mut self.proteomics {
-            if let Some(assoc) = iter.next() {
-                return Some(assoc.unwrap());
-            }
-        }
-        if let Some(iter) = &mut self.quantitative {
-            if let Some(assoc) = iter.next() {
-                return Some(assoc.unwrap());
+        for (dataset, iter) in [
+            (AzDataset::Binary, &mut self.binary),
+            (AzDataset::Proteomics, &mut self.proteomics),
+            (AzDataset::Quantitative, &mut self.quantitative),
+        ] {
+            if let Some(iter) = iter {
+                // malformed rows are skipped, as in the parallel iterator
+                if let Some(mut assoc) = iter.find_map(Result::ok) {
+                    assoc.dataset = dataset;
+                    return Some(assoc);
+                }
             }
         }
         None
@@ -449,26 +1290,135 @@ impl ParallelIterator for AzAssociations {
     where
         C: rayon::iter::plumbing::UnindexedConsumer<Self::Item>,
     {
-        if let Some(iter) = self.binary {
-            println!("BINARY");
-            iter.into_iter()
-                .par_bridge()
-                .filter_map(Result::ok)
-                .drive_unindexed(consumer)
-        } else if let Some(iter) = self.proteomics {
-            println!("PROTEOMICS");
-            iter.into_iter()
-                .par_bridge()
-                .filter_map(Result::ok)
-                .drive_unindexed(consumer)
-        } else if let Some(iter) = self.quantitative {
-            println!("QUANTITATIVE");
-            iter.into_iter()
-                .par_bridge()
+        [
+            (AzDataset::Binary, self.binary),
+            (AzDataset::Proteomics, self.proteomics),
+            (AzDataset::Quantitative, self.quantitative),
+        ]
+        .into_iter()
+        .filter_map(|(dataset, iter)| Some((dataset, iter?)))
+        .collect::<Vec<_>>()
+        .into_par_iter()
+        .flat_map(|(dataset, iter)| {
+            iter.par_bridge()
                 .filter_map(Result::ok)
-                .drive_unindexed(consumer)
-        } else {
-            consumer.into_folder().complete()
-        }
+                .map(move |mut assoc: AzAssociation| {
+                    assoc.dataset = dataset;
+                    assoc
+                })
+        })
+        .drive_unindexed(consumer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn genes(genes: &[&str]) -> Vec<String> {
+        genes.iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn mapped_gene_intragenic() {
+        assert_eq!(
+            parse_mapped_gene("PRKN, COL5A2"),
+            (genes(&["COL5A2", "PRKN"]), vec![], GeneContext::Intragenic)
+        );
+        assert_eq!(
+            parse_mapped_gene("TSHR; tshr"),
+            (genes(&["TSHR"]), vec![], GeneContext::Intragenic)
+        );
+    }
+
+    #[test]
+    fn mapped_gene_intergenic() {
+        assert_eq!(
+            parse_mapped_gene("TSHR - LINC01234"),
+            (
+                vec![],
+                genes(&["TSHR", "LINC01234"]),
+                GeneContext::Intergenic
+            )
+        );
+    }
+
+    #[test]
+    fn mapped_gene_interaction() {
+        assert_eq!(
+            parse_mapped_gene("GENEA x GENEB - GENEC"),
+            (
+                genes(&["GENEA"]),
+                genes(&["GENEB", "GENEC"]),
+                GeneContext::Interaction
+            )
+        );
+    }
+
+    #[test]
+    fn mapped_gene_hyphenated_symbol() {
+        assert_eq!(
+            parse_mapped_gene("HLA-DRB1"),
+            (genes(&["HLA-DRB1"]), vec![], GeneContext::Intragenic)
+        );
+        assert_eq!(
+            parse_mapped_gene("HLA-DRB1 - HLA-DQA1"),
+            (
+                vec![],
+                genes(&["HLA-DRB1", "HLA-DQA1"]),
+                GeneContext::Intergenic
+            )
+        );
+    }
+
+    #[test]
+    fn mapped_gene_empty() {
+        assert_eq!(
+            parse_mapped_gene(" "),
+            (vec![], vec![], GeneContext::Intragenic)
+        );
+    }
+
+    #[test]
+    fn reported_gene() {
+        assert_eq!(
+            parse_reported_gene("TSHR, gtf2a1; TSHR"),
+            genes(&["GTF2A1", "TSHR"])
+        );
+        assert!(parse_reported_gene("NR").is_empty());
+        assert!(parse_reported_gene("intergenic").is_empty());
+    }
+
+    #[test]
+    fn ci() {
+        assert_eq!(
+            parse_ci("[1.05-1.2]"),
+            (Some(1.05), Some(1.2), String::new())
+        );
+        assert_eq!(
+            parse_ci("[0.01-0.03] unit decrease"),
+            (Some(0.01), Some(0.03), "unit decrease".to_string())
+        );
+        assert_eq!(
+            parse_ci("[-0.5--0.1]"),
+            (Some(-0.5), Some(-0.1), String::new())
+        );
+        assert_eq!(
+            parse_ci("[1e-5-2E-3]"),
+            (Some(1e-5), Some(2e-3), String::new())
+        );
+        assert_eq!(parse_ci("[NR]"), (None, None, String::new()));
+        assert_eq!(
+            parse_ci("unit increase"),
+            (None, None, "unit increase".to_string())
+        );
+    }
+
+    #[test]
+    fn p_value() {
+        assert_eq!(parse_p_value("2E-8"), Some(2e-8));
+        assert_eq!(parse_p_value(" 3e-12 (adjusted)"), Some(3e-12));
+        assert_eq!(parse_p_value("NR"), None);
+        assert_eq!(parse_p_value(""), None);
     }
 }
@@ -0,0 +1,229 @@
+use std::{
+    collections::{BTreeSet, HashMap, VecDeque},
+    io::IsTerminal,
+    ops::Bound,
+    path::PathBuf,
+};
+
+use dialoguer::{theme::ColorfulTheme, Completion, FuzzySelect, History, Input, Select};
+use search_gwas::{
+    data::{AssociationRecord, GeneSource},
+    error::{Error, Result},
+    files::history_path,
+    query::{parse_genes, Filter},
+    Catalog,
+};
+
+use crate::{
+    cli::resolve_genes,
+    output::{print_trait, Display},
+    Context,
+};
+
+const MAX_HISTORY: usize = 500;
+
+/// Gene entries, most recent first, persisted to the data directory so they
+/// are kept across sessions.
+struct FileHistory {
+    path: PathBuf,
+    entries: VecDeque<String>,
+}
+
+impl FileHistory {
+    fn load(path: PathBuf) -> Self {
+        let entries = match std::fs::read_to_string(&path) {
+            Ok(file) => file.lines().rev().map(String::from).collect(),
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    log::warn!("failed to read {}: {e}", path.display());
+                }
+                VecDeque::new()
+            },
+        };
+        Self { path, entries }
+    }
+}
+
+impl<T: ToString> History<T> for FileHistory {
+    fn read(&self, pos: usize) -> Option<String> {
+        self.entries.get(pos).cloned()
+    }
+
+    fn write(&mut self, val: &T) {
+        let val = val.to_string();
+        if val.trim().is_empty() {
+            return;
+        }
+        self.entries.retain(|i| *i != val);
+        self.entries.push_front(val);
+        self.entries.truncate(MAX_HISTORY);
+        let mut file = self
+            .entries
+            .iter()
+            .rev()
+            .map(String::as_str)
+            .collect::<Vec<_>>()
+            .join("\n");
+        file.push('\n');
+        if let Err(e) = std::fs::write(&self.path, file) {
+            log::warn!("failed to write {}: {e}", self.path.display());
+        }
+    }
+}
+
+/// Completes the last of the comma separated genes to the first mapped or
+/// reported gene it is a prefix of.
+struct GeneCompletion<'a> {
+    genes: BTreeSet<&'a str>,
+}
+
+impl<'a> GeneCompletion<'a> {
+    fn new(catalog: &'a Catalog) -> Self {
+        Self {
+            genes: catalog
+                .associations()
+                .iter()
+                .flat_map(|i| i.genes(GeneSource::Both, true))
+                .collect(),
+        }
+    }
+}
+
+impl Completion for GeneCompletion<'_> {
+    fn get(&self, input: &str) -> Option<String> {
+        let (head, last) = input.split_at(input.rfind(',').map_or(0, |i| i + 1));
+        let prefix = last.trim().to_uppercase();
+        if prefix.is_empty() {
+            return None;
+        }
+        let gene = self
+            .genes
+            .range::<str, _>((Bound::Included(prefix.as_str()), Bound::Unbounded))
+            .next()
+            .filter(|i| i.starts_with(&prefix))?;
+        let space = &last[..last.len() - last.trim_start().len()];
+        Some(format!("{head}{space}{gene}"))
+    }
+}
+
+/// Where the loop goes next.
+enum Step {
+    Trait,
+    Genes,
+    Query,
+}
+
+#[inline]
+fn terminal(e: dialoguer::Error) -> Error {
+    match e {
+        dialoguer::Error::IO(e) => Error::Terminal(e),
+    }
+}
+
+/// Repeatedly picks a trait and genes to query against the loaded `catalog`
+/// until the user quits.
+pub fn run(
+    ctx: &Context,
+    catalog: &Catalog,
+    mut filter: Filter,
+    mut display: Display,
+) -> Result<()> {
+    // the prompts would otherwise spin on end of input
+    if !std::io::stdin().is_terminal() {
+        return Err(Error::Terminal(std::io::Error::other(
+            "standard input is not a terminal",
+        )));
+    }
+    let theme = ColorfulTheme::default();
+    let mut efos = catalog.efos().iter().collect::<Vec<_>>();
+    efos.sort_unstable_by(|a, b| a.label.cmp(&b.label));
+    let items = efos
+        .iter()
+        .map(|i| format!("{} ({})", i.label, i.id))
+        .collect::<Vec<_>>();
+    let completion = GeneCompletion::new(catalog);
+    let mut history = FileHistory::load(history_path(&ctx.dir));
+    let mut include_descendants = false;
+    let mut selected = 0;
+    let mut genes = Vec::new();
+    let mut step = Step::Trait;
+    println!("Type to search, Enter to select, Esc to quit");
+    loop {
+        step = match step {
+            Step::Trait => {
+                match FuzzySelect::with_theme(&theme)
+                    .with_prompt("Trait")
+                    .items(&items)
+                    .default(selected)
+                    .max_length(10)
+                    .interact_opt()
+                    .map_err(terminal)?
+                {
+                    Some(i) => {
+                        selected = i;
+                        Step::Genes
+                    },
+                    None => return Ok(()),
+                }
+            },
+            Step::Genes => {
+                let input = Input::<String>::with_theme(&theme)
+                    .with_prompt("Genes (comma separated, Tab to complete, empty for all)")
+                    .allow_empty(true)
+                    .history_with(&mut history)
+                    .completion_with(&completion)
+                    .interact_text()
+                    .map_err(terminal)?;
+                genes = resolve_genes(catalog, parse_genes(&[input]), &mut filter);
+                Step::Query
+            },
+            Step::Query => {
+                let efo = efos[selected];
+                let terms = if include_descendants {
+                    catalog.descendants(efo, None)
+                } else {
+                    HashMap::from([(efo.id, efo)])
+                };
+                print_trait(
+                    catalog.query_trait(efo, terms, &filter),
+                    genes.clone(),
+                    display,
+                    filter.threshold,
+                );
+                let actions = [
+                    "Query other genes",
+                    "Query another trait",
+                    if display.with_associations {
+                        "Hide association details"
+                    } else {
+                        "Show association details"
+                    },
+                    if include_descendants {
+                        "Exclude descendant terms"
+                    } else {
+                        "Include descendant terms"
+                    },
+                    "Quit",
+                ];
+                match Select::with_theme(&theme)
+                    .items(&actions)
+                    .default(0)
+                    .interact_opt()
+                    .map_err(terminal)?
+                {
+                    Some(0) => Step::Genes,
+                    Some(1) => Step::Trait,
+                    Some(2) => {
+                        display.with_associations = !display.with_associations;
+                        Step::Query
+                    },
+                    Some(3) => {
+                        include_descendants = !include_descendants;
+                        Step::Query
+                    },
+                    _ => return Ok(()),
+                }
+            },
+        };
+    }
+}
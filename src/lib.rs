@@ -0,0 +1,28 @@
+//! Search the [GWAS Catalog](https://www.ebi.ac.uk/gwas/) for associations.
+//!
+//! Load a [`Catalog`] from a data directory populated by
+//! [`files::check_for_updates`] and query it by trait, gene, variant or
+//! region:
+//!
+//! ```no_run
+//! use search_gwas::{files::get_data_dir, query::Filter, Catalog};
+//!
+//! let catalog = Catalog::load(&get_data_dir())?;
+//! let efo = catalog.find_efo("HYPOTHYROIDISM").unwrap();
+//! let terms = catalog.descendants(efo, None);
+//! let matches = catalog.query_trait(efo, terms, &Filter::new(5e-8));
+//! for gene in matches.genes().keys() {
+//!     println!("{gene}");
+//! }
+//! # Ok::<(), search_gwas::error::Error>(())
+//! ```
+
+mod catalog;
+pub mod config;
+pub mod consts;
+pub mod data;
+pub mod error;
+pub mod files;
+pub mod query;
+
+pub use catalog::Catalog;
@@ -1,20 +1,23 @@
 mod cli;
-mod consts;
-mod data;
-mod files;
-mod query;
+mod interactive;
+mod output;
 
 use std::path::PathBuf;
 
 use clap::Parser;
 
-use crate::{
-    cli::{Cli, Run},
+use search_gwas::{
+    config::Config,
+    error::{Error, Result},
     files::get_data_dir,
 };
 
+use crate::cli::{Cli, Run};
+
 struct Context {
     dir: PathBuf,
+    config: Config,
+    offline: bool,
 }
 
 fn main() {
@@ -25,12 +28,20 @@ fn main() {
 
     let cli = Cli::parse();
 
-    let dir = get_data_dir();
-    if !dir.exists() {
-        std::fs::create_dir(&dir).unwrap();
+    if let Err(e) = run(cli, get_data_dir()) {
+        eprintln!("Error: {e}");
+        std::process::exit(e.exit_code());
     }
+}
 
-    let ctx = Context { dir };
+fn run(cli: Cli, dir: PathBuf) -> Result<()> {
+    std::fs::create_dir_all(&dir).map_err(Error::cache(&dir))?;
+    let config = Config::load(&dir)?;
+    let ctx = Context {
+        dir,
+        config,
+        offline: false,
+    };
 
-    cli.run(ctx);
+    cli.run(ctx)
 }
@@ -0,0 +1,788 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+};
+
+use prettytable::{row, Cell, Row, Table};
+use search_gwas::{
+    data::{AssociationRecord, AzAssociation, AzDataset, GeneContext, GeneSource},
+    query::{
+        EfoMatch, GeneMatches, GenePartition, GeneSummary, RegionMatches, TraitMatches,
+        VariantMatches,
+    },
+    Catalog,
+};
+use serde::Serialize;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable tables and lists
+    #[default]
+    Table,
+    /// Tables as CSV, same layout as `table`
+    Csv,
+    /// One JSON array of records
+    Json,
+    /// One JSON record per line
+    Ndjson,
+    /// One tab separated record per line with a header
+    Tsv,
+}
+
+impl OutputFormat {
+    /// Whether this format emits flat [`Record`]s instead of the layout of
+    /// `table`.
+    #[inline]
+    pub fn is_records(self) -> bool {
+        matches!(self, Self::Json | Self::Ndjson | Self::Tsv)
+    }
+}
+
+/// How query results are printed.
+#[derive(Clone, Copy, Debug)]
+pub struct Display {
+    pub(crate) with_associations: bool,
+    pub(crate) with_pubmed_links: bool,
+    pub(crate) format: OutputFormat,
+}
+
+impl Display {
+    #[inline]
+    fn csv(&self) -> bool {
+        self.format == OutputFormat::Csv
+    }
+}
+
+/// One significant association, the stable schema for machine readable
+/// output of every query command.
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    /// The queried trait
+    #[serde(rename = "trait")]
+    pub(crate) trait_: &'a str,
+    /// The EFO term the association matched, `None` for AZ PheWAS results
+    pub(crate) matched_id: Option<String>,
+    /// The EFO label or AZ PheWAS phenotype the association matched
+    pub(crate) matched_label: &'a str,
+    pub(crate) genes: Vec<&'a str>,
+    pub(crate) p_value: f64,
+    pub(crate) accession: Option<String>,
+    pub(crate) pubmed: Option<u32>,
+    pub(crate) dataset: Option<AzDataset>,
+    /// The genes named by the study's authors, empty for AZ PheWAS results
+    pub(crate) reported_genes: Vec<&'a str>,
+    /// Whether the queried genes matched the mapped or reported genes, `None`
+    /// without a gene query
+    pub(crate) gene_source: Option<GeneSource>,
+}
+
+const TSV_HEADER: [&str; 10] = [
+    "trait",
+    "matched_id",
+    "matched_label",
+    "genes",
+    "p_value",
+    "accession",
+    "pubmed",
+    "dataset",
+    "reported_genes",
+    "gene_source",
+];
+
+pub fn write_records(format: OutputFormat, records: &[Record]) {
+    let mut stdout = std::io::stdout().lock();
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut stdout, records).unwrap();
+            writeln!(stdout).unwrap();
+        },
+        OutputFormat::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut stdout, record).unwrap();
+                writeln!(stdout).unwrap();
+            }
+        },
+        OutputFormat::Tsv => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(b'\t')
+                .from_writer(stdout);
+            writer.write_record(TSV_HEADER).unwrap();
+            for record in records {
+                writer
+                    .write_record([
+                        record.trait_,
+                        record.matched_id.as_deref().unwrap_or_default(),
+                        record.matched_label,
+                        &record.genes.join(","),
+                        &format!("{:e}", record.p_value),
+                        record.accession.as_deref().unwrap_or_default(),
+                        &record.pubmed.map(|i| i.to_string()).unwrap_or_default(),
+                        &record.dataset.map(|i| i.to_string()).unwrap_or_default(),
+                        &record.reported_genes.join(","),
+                        record.gene_source.map_or("", GeneSource::name),
+                    ])
+                    .unwrap();
+            }
+            writer.flush().unwrap();
+        },
+        OutputFormat::Table | OutputFormat::Csv => {
+            unreachable!("tables are written by the print functions")
+        },
+    }
+}
+
+const ASSOCIATION_TITLES: [&str; 7] = [
+    "Risk Allele",
+    "RAF",
+    "Effect",
+    "95% CI",
+    "P-value",
+    "Accession ID",
+    "PubMed ID",
+];
+
+/// Cells matching [`ASSOCIATION_TITLES`].
+fn association_cells(assoc: &impl AssociationRecord, with_pubmed_links: bool) -> Vec<Cell> {
+    let effect = match assoc.effect() {
+        Some(effect) if assoc.is_odds_ratio() => format!("{effect} (OR)"),
+        Some(effect) if assoc.effect_unit().is_empty() => effect.to_string(),
+        Some(effect) => format!("{effect} {}", assoc.effect_unit()),
+        None => String::new(),
+    };
+    let ci = match (assoc.ci_lower(), assoc.ci_upper()) {
+        (Some(lower), Some(upper)) => format!("{lower}-{upper}"),
+        _ => String::new(),
+    };
+    vec![
+        Cell::new(assoc.risk_allele()),
+        Cell::new(
+            &assoc
+                .risk_allele_frequency()
+                .map(|i| i.to_string())
+                .unwrap_or_default(),
+        ),
+        Cell::new(&effect),
+        Cell::new(&ci),
+        Cell::new(&format!("{:e}", assoc.p_value())),
+        Cell::new(&assoc.accession()),
+        Cell::new(&pubmed(assoc, with_pubmed_links)),
+    ]
+}
+
+#[inline]
+fn pubmed(assoc: &impl AssociationRecord, with_pubmed_links: bool) -> String {
+    if with_pubmed_links {
+        format!("https://pubmed.ncbi.nlm.nih.gov/{}", assoc.pubmed())
+    } else {
+        assoc.pubmed().to_string()
+    }
+}
+
+/// The genes of `assoc` laid out like `MAPPED_GENE`.
+fn mapped_genes(assoc: &impl AssociationRecord) -> String {
+    let genes = assoc.genes(GeneSource::Mapped, true).collect::<Vec<_>>();
+    match assoc.gene_context() {
+        GeneContext::Intragenic => genes.join(", "),
+        GeneContext::Intergenic => genes.join(" - "),
+        GeneContext::Interaction => genes.join(" x "),
+    }
+}
+
+#[inline]
+fn reported_genes(assoc: &impl AssociationRecord) -> String {
+    assoc.reported_gene().collect::<Vec<_>>().join(", ")
+}
+
+#[inline]
+fn titles(titles: Vec<&str>) -> Row {
+    Row::new(titles.into_iter().map(Cell::new).collect())
+}
+
+fn print_table(table: Table, indent: &str, csv: bool) {
+    if csv {
+        let mut buf = Vec::new();
+        table.to_csv(&mut buf).unwrap();
+        String::from_utf8(buf)
+            .unwrap()
+            .lines()
+            .for_each(|i| println!("{indent}{i}"));
+    } else {
+        table
+            .to_string()
+            .lines()
+            .for_each(|i| println!("{indent}{i}"));
+    }
+}
+
+/// Prints the genes of `partition`, with the labels of the terms they matched
+/// if `labelled` and the genes they were found in if `with_source`.
+fn print_partition(partition: GenePartition, labelled: bool, with_source: bool, csv: bool) {
+    if !partition.associated.is_empty() {
+        println!("  ASSOCIATED:");
+        if csv {
+            println!(
+                "    {}",
+                partition
+                    .associated
+                    .into_iter()
+                    .map(|(gene, ..)| gene)
+                    .collect::<Vec<_>>()
+                    .join(",")
+            );
+        } else {
+            for (gene, labels, source) in partition.associated {
+                let mut line = format!("    {gene}");
+                if labelled {
+                    line += &format!(" ({})", labels.into_iter().collect::<Vec<_>>().join(", "));
+                }
+                if with_source {
+                    line += &format!(" [{source}]");
+                }
+                println!("{line}");
+            }
+        }
+    }
+    if !partition.not_associated.is_empty() {
+        println!("  NOT ASSOCIATED:");
+        if csv {
+            println!("    {}", partition.not_associated.join(","));
+        } else {
+            for gene in partition.not_associated {
+                println!("    {gene}");
+            }
+        }
+    }
+}
+
+pub fn print_efo_search(query: &str, matches: Vec<EfoMatch>, csv: bool) {
+    println!("{query}:");
+    if matches.is_empty() {
+        println!("  No matching EFO terms found");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_titles(row!["EFO ID", "Label", "Matched Synonym", "Score"]);
+    for i in matches {
+        table.add_row(row![
+            i.efo.id,
+            i.efo.label,
+            if i.is_synonym() { i.name } else { "" },
+            format!("{:.2}", i.score),
+        ]);
+    }
+    print_table(table, "  ", csv);
+}
+
+pub fn print_trait<A: AssociationRecord>(
+    matches: TraitMatches<A>,
+    genes: Vec<String>,
+    display: Display,
+    threshold: f64,
+) {
+    let csv = display.csv();
+    if display.format.is_records() {
+        let records = matches
+            .associations
+            .iter()
+            .filter_map(|assoc| {
+                let source = genes
+                    .iter()
+                    .filter_map(|i| matches.gene_source(assoc, i))
+                    .reduce(GeneSource::union);
+                (genes.is_empty() || source.is_some()).then_some((assoc, source))
+            })
+            .flat_map(|(assoc, source)| {
+                matches
+                    .matched_terms(assoc)
+                    .into_iter()
+                    .map(move |term| Record {
+                        trait_: &matches.efo.label,
+                        matched_id: Some(term.id.to_string()),
+                        matched_label: &term.label,
+                        genes: assoc.genes(GeneSource::Mapped, true).collect(),
+                        p_value: assoc.p_value(),
+                        accession: Some(assoc.accession()),
+                        pubmed: Some(assoc.pubmed()),
+                        dataset: None,
+                        reported_genes: assoc.reported_gene().collect(),
+                        gene_source: source,
+                    })
+            })
+            .collect::<Vec<_>>();
+        write_records(display.format, &records);
+        return;
+    }
+    let labelled = matches.is_labelled();
+    let with_reported = matches.gene_source != GeneSource::Mapped;
+    // only ambiguous when both sources are searched
+    let with_source = matches.gene_source == GeneSource::Both;
+    println!("{} (p < {threshold:e}):", matches.efo.label);
+    if matches.associations.is_empty() {
+        println!("  No significant associations found");
+    } else if genes.is_empty() {
+        if display.with_associations {
+            let mut table = Table::new();
+            let mut header = Vec::new();
+            if labelled {
+                header.push("Trait");
+            }
+            header.push("Genes");
+            if with_reported {
+                header.push("Reported Genes");
+            }
+            header.extend(ASSOCIATION_TITLES);
+            table.set_titles(titles(header));
+            for &assoc in &matches.associations {
+                let mut cells = Vec::new();
+                if labelled {
+                    cells.push(Cell::new(&matched_labels(&matches, assoc)));
+                }
+                cells.push(Cell::new(&mapped_genes(assoc)));
+                if with_reported {
+                    cells.push(Cell::new(&reported_genes(assoc)));
+                }
+                cells.extend(association_cells(assoc, display.with_pubmed_links));
+                table.add_row(Row::new(cells));
+            }
+            print_table(table, "  ", csv);
+        } else {
+            let genes = matches.genes();
+            if csv {
+                println!("{}", genes.into_keys().collect::<Vec<_>>().join(","));
+            } else {
+                for (gene, labels) in genes {
+                    if labelled {
+                        println!(
+                            "  {gene} ({})",
+                            labels.into_iter().collect::<Vec<_>>().join(", ")
+                        );
+                    } else {
+                        println!("  {gene}");
+                    }
+                }
+            }
+        }
+    } else if display.with_associations {
+        for gene in genes {
+            let assocs = matches.for_gene(&gene);
+            println!("  {gene}:");
+            if assocs.is_empty() {
+                if !csv {
+                    println!("    NONE");
+                }
+            } else {
+                let mut table = Table::new();
+                let mut header = Vec::new();
+                if labelled {
+                    header.push("Trait");
+                }
+                if with_source {
+                    header.push("Source");
+                }
+                header.extend(ASSOCIATION_TITLES);
+                table.set_titles(titles(header));
+                for assoc in assocs {
+                    let mut cells = Vec::new();
+                    if labelled {
+                        cells.push(Cell::new(&matched_labels(&matches, assoc)));
+                    }
+                    if let Some(source) = matches.gene_source(assoc, &gene).filter(|_| with_source)
+                    {
+                        cells.push(Cell::new(&source.to_string()));
+                    }
+                    cells.extend(association_cells(assoc, display.with_pubmed_links));
+                    table.add_row(Row::new(cells));
+                }
+                print_table(table, "    ", csv);
+            }
+        }
+    } else {
+        print_partition(matches.partition(genes), labelled, with_source, csv);
+    }
+}
+
+#[inline]
+fn matched_labels<A: AssociationRecord>(matches: &TraitMatches<A>, assoc: &A) -> String {
+    matches
+        .matched_terms(assoc)
+        .into_iter()
+        .map(|i| i.label.as_str())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+#[inline]
+fn trait_labels(catalog: &Catalog, assoc: &impl AssociationRecord) -> String {
+    assoc
+        .traits()
+        .map(|i| catalog.term_label(i))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+pub fn print_gene<A: AssociationRecord>(
+    catalog: &Catalog,
+    matches: GeneMatches<A>,
+    display: Display,
+    threshold: f64,
+) {
+    let csv = display.csv();
+    // only ambiguous when both sources are searched
+    let with_source = matches.gene_source == GeneSource::Both;
+    println!("{} (p < {threshold:e}):", matches.gene);
+    if matches.associations.is_empty() {
+        println!("  No significant associations found");
+    } else if display.with_associations {
+        let mut table = Table::new();
+        let mut header = vec!["Trait"];
+        if with_source {
+            header.push("Source");
+        }
+        header.extend(ASSOCIATION_TITLES);
+        table.set_titles(titles(header));
+        for &assoc in &matches.associations {
+            let mut cells = vec![Cell::new(&trait_labels(catalog, assoc))];
+            if let Some(source) = matches.gene_source(assoc).filter(|_| with_source) {
+                cells.push(Cell::new(&source.to_string()));
+            }
+            cells.extend(association_cells(assoc, display.with_pubmed_links));
+            table.add_row(Row::new(cells));
+        }
+        print_table(table, "  ", csv);
+    } else {
+        let mut labels = BTreeMap::<String, GeneSource>::new();
+        for &assoc in &matches.associations {
+            let source = match matches.gene_source(assoc) {
+                Some(source) => source,
+                None => continue,
+            };
+            for i in assoc.traits() {
+                labels
+                    .entry(catalog.term_label(i))
+                    .and_modify(|i| *i = i.union(source))
+                    .or_insert(source);
+            }
+        }
+        if csv {
+            println!("  {}", labels.into_keys().collect::<Vec<_>>().join(","));
+        } else {
+            for (label, source) in labels {
+                if with_source {
+                    println!("  {label} [{source}]");
+                } else {
+                    println!("  {label}");
+                }
+            }
+        }
+    }
+}
+
+pub fn print_variant<A: AssociationRecord>(
+    catalog: &Catalog,
+    matches: VariantMatches<A>,
+    display: Display,
+    threshold: f64,
+) {
+    println!("{} (p < {threshold:e}):", matches.variant);
+    if matches.associations.is_empty() {
+        println!("  No significant associations found");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_titles(row![
+        "Trait",
+        "Genes",
+        "Risk Allele",
+        "P-value",
+        "Accession ID",
+        "PubMed ID"
+    ]);
+    for assoc in matches.associations {
+        table.add_row(row![
+            trait_labels(catalog, assoc),
+            mapped_genes(assoc),
+            assoc.strongest_snp_risk_allele(),
+            format!("{:e}", assoc.p_value()),
+            assoc.accession(),
+            pubmed(assoc, display.with_pubmed_links),
+        ]);
+    }
+    print_table(table, "  ", display.csv());
+}
+
+pub fn print_region<A: AssociationRecord>(
+    catalog: &Catalog,
+    matches: RegionMatches<A>,
+    display: Display,
+    threshold: f64,
+) {
+    println!("{} (p < {threshold:e}):", matches.region);
+    if matches.associations.is_empty() {
+        println!("  No significant associations found");
+        return;
+    }
+    let mut table = Table::new();
+    table.set_titles(row![
+        "Position",
+        "Trait",
+        "Genes",
+        "Risk Allele",
+        "P-value",
+        "Accession ID",
+        "PubMed ID"
+    ]);
+    for assoc in matches.associations {
+        table.add_row(row![
+            assoc
+                .locations()
+                .filter(|(chr, pos)| matches.region.contains(chr, *pos))
+                .map(|(chr, pos)| format!("{chr}:{pos}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+            trait_labels(catalog, assoc),
+            mapped_genes(assoc),
+            assoc.strongest_snp_risk_allele(),
+            format!("{:e}", assoc.p_value()),
+            assoc.accession(),
+            pubmed(assoc, display.with_pubmed_links),
+        ]);
+    }
+    print_table(table, "  ", display.csv());
+}
+
+pub fn print_az(
+    term: &str,
+    results: Vec<&AzAssociation>,
+    genes: Vec<String>,
+    display: Display,
+    threshold: f64,
+) {
+    let csv = display.csv();
+    if display.format.is_records() {
+        let records = results
+            .iter()
+            .filter(|result| {
+                genes.is_empty()
+                    || genes
+                        .iter()
+                        .any(|i| result.mapped_gene.contains(i.as_str()))
+            })
+            .map(|result| Record {
+                trait_: term,
+                matched_id: None,
+                matched_label: &result.trait_,
+                genes: vec![&result.mapped_gene],
+                p_value: result.p_value,
+                accession: None,
+                pubmed: None,
+                dataset: Some(result.dataset),
+                reported_genes: Vec::new(),
+                gene_source: None,
+            })
+            .collect::<Vec<_>>();
+        write_records(display.format, &records);
+        return;
+    }
+    println!("{term} (p < {threshold:e}):");
+    if results.is_empty() {
+        println!("  No significant associations found");
+    } else if genes.is_empty() {
+        if display.with_associations {
+            let mut table = Table::new();
+            table.set_titles(row!["Dataset", "Trait", "Genes", "P-value"]);
+            for assoc in results {
+                table.add_row(row![
+                    assoc.dataset,
+                    assoc.trait_,
+                    assoc.mapped_gene,
+                    format!("{:e}", assoc.p_value),
+                ]);
+            }
+            print_table(table, "  ", csv);
+        } else {
+            let genes = results
+                .iter()
+                .map(|result| result.mapped_gene.as_str())
+                .collect::<BTreeSet<_>>();
+            if csv {
+                println!("{}", genes.into_iter().collect::<Vec<_>>().join(","));
+            } else {
+                for gene in genes {
+                    println!("  {gene}");
+                }
+            }
+        }
+    } else if display.with_associations {
+        for gene in genes {
+            let assocs = results
+                .iter()
+                .filter(|result| result.mapped_gene.contains(&gene))
+                .collect::<Vec<_>>();
+            println!("  {gene}:");
+            if assocs.is_empty() {
+                if !csv {
+                    println!("    NONE");
+                }
+            } else {
+                let mut table = Table::new();
+                table.set_titles(row!["Dataset", "Trait", "P-value"]);
+                for assoc in assocs {
+                    table.add_row(row![
+                        assoc.dataset,
+                        assoc.trait_,
+                        format!("{:e}", assoc.p_value),
+                    ]);
+                }
+                print_table(table, "    ", csv);
+            }
+        }
+    } else {
+        let (associated, not_associated) = genes.into_iter().partition::<Vec<_>, _>(|gene| {
+            results
+                .iter()
+                .any(|result| result.mapped_gene.contains(gene))
+        });
+        print_partition(
+            GenePartition {
+                associated: associated
+                    .into_iter()
+                    .map(|gene| (gene, BTreeSet::new(), GeneSource::Mapped))
+                    .collect(),
+                not_associated,
+            },
+            false,
+            false,
+            csv,
+        );
+    }
+}
+
+/// One trait of `batch` with the summary of each queried gene, in order.
+#[derive(Debug)]
+pub struct BatchTrait<'a> {
+    pub(crate) label: &'a str,
+    pub(crate) summaries: Vec<Option<GeneSummary>>,
+}
+
+/// One trait and gene with at least one significant association, the schema
+/// of the long `batch` output.
+#[derive(Debug, Serialize)]
+struct BatchRecord<'a> {
+    #[serde(rename = "trait")]
+    trait_: &'a str,
+    gene: &'a str,
+    best_p_value: f64,
+    n_associations: usize,
+    studies: &'a [String],
+}
+
+/// Prints the results of `batch`, either one row per trait and associated gene
+/// or, with `matrix`, a trait by gene matrix of the best p-values.
+pub fn print_batch(genes: &[String], traits: &[BatchTrait], format: OutputFormat, matrix: bool) {
+    if matrix {
+        let mut header = vec!["Trait"];
+        header.extend(genes.iter().map(String::as_str));
+        let rows = traits.iter().map(|i| {
+            std::iter::once(i.label.to_string())
+                .chain(i.summaries.iter().map(|i| {
+                    i.as_ref()
+                        .map(|i| format!("{:e}", i.best_p_value))
+                        .unwrap_or_default()
+                }))
+                .collect::<Vec<_>>()
+        });
+        if format == OutputFormat::Tsv {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(b'\t')
+                .from_writer(std::io::stdout().lock());
+            writer.write_record(header).unwrap();
+            for row in rows {
+                writer.write_record(row).unwrap();
+            }
+            writer.flush().unwrap();
+        } else {
+            let mut table = Table::new();
+            table.set_titles(titles(header));
+            for row in rows {
+                table.add_row(Row::new(row.iter().map(|i| Cell::new(i)).collect()));
+            }
+            print_table(table, "", format == OutputFormat::Csv);
+        }
+        return;
+    }
+    let records = traits
+        .iter()
+        .flat_map(|i| {
+            genes
+                .iter()
+                .zip(&i.summaries)
+                .filter_map(|(gene, summary)| {
+                    summary.as_ref().map(|summary| BatchRecord {
+                        trait_: i.label,
+                        gene,
+                        best_p_value: summary.best_p_value,
+                        n_associations: summary.associations,
+                        studies: &summary.studies,
+                    })
+                })
+        })
+        .collect::<Vec<_>>();
+    let mut stdout = std::io::stdout().lock();
+    match format {
+        OutputFormat::Json => {
+            serde_json::to_writer_pretty(&mut stdout, &records).unwrap();
+            writeln!(stdout).unwrap();
+        },
+        OutputFormat::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut stdout, &record).unwrap();
+                writeln!(stdout).unwrap();
+            }
+        },
+        OutputFormat::Tsv => {
+            let mut writer = csv::WriterBuilder::new()
+                .delimiter(b'\t')
+                .from_writer(stdout);
+            writer
+                .write_record(["trait", "gene", "best_p_value", "n_associations", "studies"])
+                .unwrap();
+            for record in records {
+                writer
+                    .write_record([
+                        record.trait_,
+                        record.gene,
+                        &format!("{:e}", record.best_p_value),
+                        &record.n_associations.to_string(),
+                        &record.studies.join(","),
+                    ])
+                    .unwrap();
+            }
+            writer.flush().unwrap();
+        },
+        OutputFormat::Table | OutputFormat::Csv => {
+            drop(stdout);
+            if records.is_empty() && format == OutputFormat::Table {
+                println!("No significant associations found");
+                return;
+            }
+            let mut table = Table::new();
+            table.set_titles(row![
+                "Trait",
+                "Gene",
+                "Best P-value",
+                "Associations",
+                "Studies"
+            ]);
+            for record in records {
+                table.add_row(row![
+                    record.trait_,
+                    record.gene,
+                    format!("{:e}", record.best_p_value),
+                    record.n_associations,
+                    record.studies.join(", "),
+                ]);
+            }
+            print_table(table, "", format == OutputFormat::Csv);
+        },
+    }
+}
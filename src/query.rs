@@ -1,290 +1,590 @@
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 
-use log::debug;
-use prettytable::{row, Table};
 use rayon::prelude::*;
 
 use crate::{
-    data::{Association, Efo},
-    files::AzAssociations,
+    consts::{DEFAULT_THRESHOLD, EFO_SEARCH_MIN_SCORE},
+    data::{
+        AssociationRecord, AzAssociation, AzDataset, Direction, Efo, GeneSource, GenomicRegion,
+        TermId,
+    },
 };
 
 pub fn parse_genes(genes: &[String]) -> Vec<String> {
     genes
         .iter()
         .flat_map(|i| i.split(',').map(|i| i.trim().to_uppercase()))
+        .filter(|i| !i.is_empty())
         .collect()
 }
 
-pub fn find_efo<'a>(efos: &'a [Efo], label: &str) -> Option<&'a Efo> {
-    match efos.iter().find(|i| i.label == *label) {
-        None => efos.iter().find(|i| i.synonyms.contains(label)),
-        efo => efo,
+pub fn parse_variants(variants: &[String]) -> Vec<String> {
+    variants
+        .iter()
+        .flat_map(|i| i.split(',').map(|i| i.trim().to_lowercase()))
+        .filter(|i| !i.is_empty())
+        .collect()
+}
+
+/// Which associations count as hits: significant at `threshold` and, if set,
+/// with the given direction and size of effect. Associations without an
+/// effect size never pass an active effect filter.
+#[derive(Clone, Debug)]
+pub struct Filter {
+    pub threshold: f64,
+    pub min_effect: Option<f64>,
+    pub direction: Option<Direction>,
+    // whether the genes flanking an intergenic variant match gene queries
+    pub flanking: bool,
+    // which genes of an association gene queries match against
+    pub gene_source: GeneSource,
+    // the symbols each resolved gene was given as, which associations may
+    // still list it under, e.g. `PARK2` for `PRKN`
+    pub aliases: HashMap<String, Vec<String>>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Self::new(DEFAULT_THRESHOLD)
     }
 }
 
-pub fn query(
-    efo: &Efo,
-    genes: Vec<String>,
-    associations: &[Association],
-    with_associations: bool,
-    with_pubmed_links: bool,
-    csv: bool,
-) {
-    let results = associations
-        .iter()
-        .filter(|result| result.is_significant() && result.is_associated_with(efo.id))
-        .collect::<Vec<_>>();
-    println!("{}:", efo.label);
-    if results.is_empty() {
-        println!("  No significant associations found");
-    } else if genes.is_empty() {
-        if with_associations {
-            let mut table = Table::new();
-            table.set_titles(row!["Genes", "P-value", "Accession ID", "PubMed ID"]);
-            for assoc in results {
-                let pubmed = if with_pubmed_links {
-                    format!("https://pubmed.ncbi.nlm.nih.gov/{}", assoc.pubmed)
-                } else {
-                    assoc.pubmed.to_string()
-                };
-                table.add_row(row![
-                    assoc.mapped_gene.join(", "),
-                    format!("{:e}", assoc.p_value),
-                    format!("GCST{}", assoc.accession_id.to_string()),
-                    pubmed,
-                ]);
-            }
-            if csv {
-                let mut buf = Vec::new();
-                table.to_csv(&mut buf).unwrap();
-                String::from_utf8(buf)
-                    .unwrap()
-                    .lines()
-                    .for_each(|i| println!("  {}", i));
-            } else {
-                table.to_string().lines().for_each(|i| println!("  {}", i));
-            }
-        } else {
-            let genes = results
-                .iter()
-                .flat_map(|result| result.mapped_gene.iter())
-                .collect::<HashSet<_>>();
-            if csv {
-                println!(
-                    "{}",
-                    genes
-                        .into_iter()
-                        .map(|i| i.as_str())
-                        .collect::<Vec<_>>()
-                        .join(",")
-                );
-            } else {
-                for gene in genes {
-                    println!("  {gene}");
-                }
-            }
+impl Filter {
+    pub fn new(threshold: f64) -> Self {
+        Self {
+            threshold,
+            min_effect: None,
+            direction: None,
+            flanking: true,
+            gene_source: GeneSource::Mapped,
+            aliases: HashMap::new(),
         }
-    } else if with_associations {
-        for gene in genes {
-            let assocs = results
+    }
+
+    pub fn matches(&self, assoc: &impl AssociationRecord) -> bool {
+        assoc.is_significant(self.threshold)
+            && self
+                .min_effect
+                .is_none_or(|min| assoc.effect_magnitude().is_some_and(|i| i >= min))
+            && self
+                .direction
+                .is_none_or(|direction| assoc.direction() == Some(direction))
+    }
+
+    /// The uppercase `gene` and the symbols it was given as.
+    pub fn symbols<'a>(&'a self, gene: &'a str) -> impl Iterator<Item = &'a str> {
+        symbols(&self.aliases, gene)
+    }
+}
+
+#[inline]
+fn symbols<'a>(
+    aliases: &'a HashMap<String, Vec<String>>,
+    gene: &'a str,
+) -> impl Iterator<Item = &'a str> {
+    std::iter::once(gene).chain(aliases.get(gene).into_iter().flatten().map(String::as_str))
+}
+
+/// Which genes of `assoc` list any of the `symbols` of a gene, `None` if none
+/// of them are searched.
+fn gene_source<'a>(
+    assoc: &impl AssociationRecord,
+    symbols: impl Iterator<Item = &'a str>,
+    source: GeneSource,
+    flanking: bool,
+) -> Option<GeneSource> {
+    symbols
+        .filter_map(|i| assoc.gene_source(i, flanking))
+        .filter_map(|i| source.intersect(i))
+        .reduce(GeneSource::union)
+}
+
+/// A term found by [`search_efo`] and the label or synonym it matched best.
+#[derive(Debug)]
+pub struct EfoMatch<'a> {
+    pub efo: &'a Efo,
+    pub name: &'a str,
+    // 1 for an exact match, down to 0 for nothing in common
+    pub score: f64,
+}
+
+impl EfoMatch<'_> {
+    /// Whether the term matched through one of its synonyms.
+    #[inline]
+    pub fn is_synonym(&self) -> bool {
+        self.name != self.efo.label
+    }
+}
+
+/// Ranks terms by how closely their label or one of their synonyms matches the
+/// uppercase `query`, returning at most `limit` terms scoring at least
+/// [`EFO_SEARCH_MIN_SCORE`], best first.
+///
+/// A name scores the better of its edit distance to the whole query and its
+/// word overlap with it, where words also match on a prefix or a small typo so
+/// partial queries like `THYROID DIS` still rank `THYROID DISEASE` first.
+pub fn search_efo<'a>(efos: &'a [Efo], query: &str, limit: usize) -> Vec<EfoMatch<'a>> {
+    let query = query.trim();
+    let words = words(query).collect::<Vec<_>>();
+    if words.is_empty() {
+        return Vec::new();
+    }
+    let mut matches = efos
+        .par_iter()
+        .filter_map(|efo| {
+            std::iter::once(&efo.label)
+                .chain(&efo.synonyms)
+                .map(|name| EfoMatch {
+                    efo,
+                    name,
+                    score: name_score(query, &words, name),
+                })
+                .max_by(|a, b| a.score.total_cmp(&b.score))
+                .filter(|i| i.score >= EFO_SEARCH_MIN_SCORE)
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by(|a, b| {
+        b.score
+            .total_cmp(&a.score)
+            .then_with(|| a.efo.label.cmp(&b.efo.label))
+    });
+    matches.truncate(limit);
+    matches
+}
+
+#[inline]
+fn words(s: &str) -> impl Iterator<Item = &str> {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|i| !i.is_empty())
+}
+
+fn name_score(query: &str, words: &[&str], name: &str) -> f64 {
+    if query == name {
+        return 1.0;
+    }
+    let edit = strsim::normalized_levenshtein(query, name);
+    let name_words = self::words(name).collect::<Vec<_>>();
+    let overlap = words
+        .iter()
+        .map(|word| {
+            name_words
                 .iter()
-                .filter(|result| result.mapped_gene.contains(&gene))
-                .collect::<Vec<_>>();
-            println!("  {gene}:");
-            if assocs.is_empty() {
-                if !csv {
-                    println!("    NONE");
-                }
-            } else {
-                let mut table = Table::new();
-                table.set_titles(row!["P-value", "Accession ID", "PubMed ID"]);
-                for assoc in assocs {
-                    let pubmed = if with_pubmed_links {
-                        format!("https://pubmed.ncbi.nlm.nih.gov/{}", assoc.pubmed)
-                    } else {
-                        assoc.pubmed.to_string()
-                    };
-                    table.add_row(row![
-                        format!("{:e}", assoc.p_value),
-                        format!("GCST{}", assoc.accession_id.to_string()),
-                        pubmed,
-                    ]);
-                }
-                if csv {
-                    let mut buf = Vec::new();
-                    table.to_csv(&mut buf).unwrap();
-                    String::from_utf8(buf)
-                        .unwrap()
-                        .lines()
-                        .for_each(|i| println!("    {}", i));
-                } else {
-                    table
-                        .to_string()
-                        .lines()
-                        .for_each(|i| println!("    {}", i));
-                }
-            }
-        }
+                .map(|i| word_score(word, i))
+                .fold(0.0, f64::max)
+        })
+        .sum::<f64>();
+    // Sørensen–Dice over the words, so extra words on either side count
+    // against the name
+    let overlap = 2.0 * overlap / (words.len() + name_words.len()) as f64;
+    // only an exact match scores 1
+    edit.max(overlap).min(0.99)
+}
+
+fn word_score(word: &str, name_word: &str) -> f64 {
+    if word == name_word {
+        1.0
+    } else if name_word.starts_with(word) {
+        0.9
     } else {
-        let mut associated = Vec::with_capacity(genes.len());
-        let mut not_associated = Vec::with_capacity(genes.len());
-        for gene in genes {
-            let assoc = results
-                .iter()
-                .any(|result| result.mapped_gene.contains(&gene));
-            if assoc {
-                associated.push(gene);
-            } else {
-                not_associated.push(gene);
-            }
-        }
-        if !associated.is_empty() {
-            println!("  ASSOCIATED:");
-            if csv {
-                println!(
-                    "    {}",
-                    associated.into_iter().collect::<Vec<_>>().join(",")
-                );
-            } else {
-                for gene in associated {
-                    println!("    {gene}");
-                }
-            }
-        }
-        if !not_associated.is_empty() {
-            println!("  NOT ASSOCIATED:");
-            if csv {
-                println!(
-                    "    {}",
-                    not_associated.into_iter().collect::<Vec<_>>().join(",")
-                );
-            } else {
-                for gene in not_associated {
-                    println!("    {gene}");
-                }
-            }
+        let score = strsim::normalized_levenshtein(word, name_word);
+        if score >= 0.75 {
+            score * 0.9
+        } else {
+            0.0
         }
     }
 }
 
-pub fn query_az(term: &str, genes: Vec<String>, with_associations: bool, csv: bool) {
-    let associations = AzAssociations::new();
-    debug!("Loading AZ associations...");
-    let associations = ParallelIterator::collect::<Vec<_>>(associations.into_par_iter());
-    debug!("Loaded {} AZ associations", associations.len());
-    let results = associations
-        .into_par_iter()
-        .filter(|result| result.is_significant() && result.is_associated_with(term))
-        .collect::<Vec<_>>();
-    debug!("Found {} significant associations", results.len());
-    println!("{}:", term);
-    if results.is_empty() {
-        println!("  No significant associations found");
-    } else if genes.is_empty() {
-        if with_associations {
-            let mut table = Table::new();
-            table.set_titles(row!["Trait", "Genes", "P-value"]);
-            for assoc in results {
-                table.add_row(row![
-                    assoc.trait_,
-                    assoc.mapped_gene,
-                    format!("{:e}", assoc.p_value),
-                ]);
-            }
-            if csv {
-                let mut buf = Vec::new();
-                table.to_csv(&mut buf).unwrap();
-                String::from_utf8(buf)
-                    .unwrap()
-                    .lines()
-                    .for_each(|i| println!("  {}", i));
-            } else {
-                table.to_string().lines().for_each(|i| println!("  {}", i));
-            }
-        } else {
-            let genes = results
-                .iter()
-                .map(|result| result.mapped_gene.as_str())
-                .collect::<HashSet<_>>();
-            if csv {
-                println!("{}", genes.into_iter().collect::<Vec<_>>().join(","));
-            } else {
-                for gene in genes {
-                    println!("  {gene}");
-                }
+/// Associations annotated to any of `terms`, which should include `efo`
+/// itself.
+pub fn query_trait<'a, A: AssociationRecord + 'a>(
+    associations: impl IntoIterator<Item = &'a A>,
+    efo: &'a Efo,
+    terms: HashMap<TermId, &'a Efo>,
+    filter: &Filter,
+) -> TraitMatches<'a, A> {
+    let associations = associations
+        .into_iter()
+        .filter(|i| filter.matches(*i) && i.is_associated_with(&terms))
+        .collect();
+    TraitMatches {
+        efo,
+        terms,
+        flanking: filter.flanking,
+        gene_source: filter.gene_source,
+        aliases: filter.aliases.clone(),
+        associations,
+    }
+}
+
+/// Associations mapped to the given uppercase gene symbol, or flanked by it
+/// or reported for it if the filter allows.
+pub fn query_gene<'a, A: AssociationRecord + 'a>(
+    associations: impl IntoIterator<Item = &'a A>,
+    gene: String,
+    filter: &Filter,
+) -> GeneMatches<'a, A> {
+    let associations = associations
+        .into_iter()
+        .filter(|i| {
+            filter.matches(*i)
+                && gene_source(
+                    *i,
+                    filter.symbols(&gene),
+                    filter.gene_source,
+                    filter.flanking,
+                )
+                .is_some()
+        })
+        .collect();
+    GeneMatches {
+        aliases: filter.aliases.get(&gene).cloned().unwrap_or_default(),
+        gene,
+        flanking: filter.flanking,
+        gene_source: filter.gene_source,
+        associations,
+    }
+}
+
+/// Associations reported for the given lowercase variant.
+pub fn query_variant<'a, A: AssociationRecord + 'a>(
+    associations: impl IntoIterator<Item = &'a A>,
+    variant: String,
+    filter: &Filter,
+) -> VariantMatches<'a, A> {
+    let associations = associations
+        .into_iter()
+        .filter(|i| filter.matches(*i) && i.has_variant(&variant))
+        .collect();
+    VariantMatches {
+        variant,
+        associations,
+    }
+}
+
+/// Associations located in `region`, optionally restricted to `terms`.
+pub fn query_region<'a, A: AssociationRecord + 'a>(
+    associations: impl IntoIterator<Item = &'a A>,
+    region: GenomicRegion,
+    terms: Option<&HashMap<TermId, &Efo>>,
+    filter: &Filter,
+) -> RegionMatches<'a, A> {
+    let associations = associations
+        .into_iter()
+        .filter(|i| {
+            filter.matches(*i)
+                && i.is_in_region(&region)
+                && terms.is_none_or(|terms| i.is_associated_with(terms))
+        })
+        .collect();
+    RegionMatches {
+        region,
+        associations,
+    }
+}
+
+/// Associations annotated to a trait or, with descendants included, any term
+/// below it.
+#[derive(Debug)]
+pub struct TraitMatches<'a, A> {
+    pub efo: &'a Efo,
+    // the searched terms, including `efo`
+    pub terms: HashMap<TermId, &'a Efo>,
+    // whether flanking genes count, see `Filter::flanking`
+    pub flanking: bool,
+    // which genes count, see `Filter::gene_source`
+    pub gene_source: GeneSource,
+    // see `Filter::aliases`
+    pub aliases: HashMap<String, Vec<String>>,
+    pub associations: Vec<&'a A>,
+}
+
+impl<'a, A: AssociationRecord> TraitMatches<'a, A> {
+    /// Whether more than the queried term was searched, so hits should be
+    /// labelled with the term they matched.
+    #[inline]
+    pub fn is_labelled(&self) -> bool {
+        self.terms.len() > 1
+    }
+
+    /// The searched terms that `assoc` is annotated with.
+    pub fn matched_terms(&self, assoc: &A) -> Vec<&'a Efo> {
+        assoc
+            .traits()
+            .filter_map(|i| self.terms.get(&i).copied())
+            .collect()
+    }
+
+    pub fn for_gene(&self, gene: &str) -> Vec<&'a A> {
+        self.associations
+            .iter()
+            .copied()
+            .filter(|i| self.gene_source(i, gene).is_some())
+            .collect()
+    }
+
+    /// Every associated gene with the labels of the terms it matched.
+    pub fn genes(&self) -> BTreeMap<&'a str, BTreeSet<&'a str>> {
+        let mut genes = BTreeMap::<&str, BTreeSet<&str>>::new();
+        for assoc in &self.associations {
+            let labels = self.matched_terms(assoc);
+            for gene in assoc.genes(self.gene_source, self.flanking) {
+                genes
+                    .entry(gene)
+                    .or_default()
+                    .extend(labels.iter().map(|i| i.label.as_str()));
             }
         }
-    } else if with_associations {
-        for gene in genes {
-            let assocs = results
-                .iter()
-                .filter(|result| result.mapped_gene.contains(&gene))
+        genes
+    }
+
+    /// Which searched genes of `assoc` list `gene`, `None` if it doesn't
+    /// match.
+    pub fn gene_source(&self, assoc: &A, gene: &str) -> Option<GeneSource> {
+        gene_source(
+            assoc,
+            symbols(&self.aliases, gene),
+            self.gene_source,
+            self.flanking,
+        )
+    }
+
+    /// Summarizes the associations of each of the uppercase `genes`, `None`
+    /// for genes without any. Each association is only visited once, so this
+    /// scales to long gene lists unlike calling [`for_gene`](Self::for_gene)
+    /// for each.
+    pub fn summarize(&self, genes: &[String]) -> Vec<Option<GeneSummary>> {
+        let positions = genes
+            .iter()
+            .enumerate()
+            .flat_map(|(i, gene)| symbols(&self.aliases, gene).map(move |gene| (gene, i)))
+            .collect::<HashMap<_, _>>();
+        let mut summaries = genes.iter().map(|_| None).collect::<Vec<_>>();
+        for assoc in &self.associations {
+            let mut matched = assoc
+                .genes(self.gene_source, self.flanking)
+                .filter_map(|i| positions.get(i).copied())
                 .collect::<Vec<_>>();
-            println!("  {gene}:");
-            if assocs.is_empty() {
-                if !csv {
-                    println!("    NONE");
-                }
-            } else {
-                let mut table = Table::new();
-                table.set_titles(row!["Trait", "P-value"]);
-                for assoc in assocs {
-                    table.add_row(row![assoc.trait_, format!("{:e}", assoc.p_value),]);
-                }
-                if csv {
-                    let mut buf = Vec::new();
-                    table.to_csv(&mut buf).unwrap();
-                    String::from_utf8(buf)
-                        .unwrap()
-                        .lines()
-                        .for_each(|i| println!("    {}", i));
-                } else {
-                    table
-                        .to_string()
-                        .lines()
-                        .for_each(|i| println!("    {}", i));
-                }
+            matched.sort_unstable();
+            matched.dedup();
+            for i in matched {
+                let summary = summaries[i].get_or_insert_with(|| GeneSummary {
+                    best_p_value: f64::INFINITY,
+                    associations: 0,
+                    studies: Vec::new(),
+                });
+                summary.best_p_value = summary.best_p_value.min(assoc.p_value());
+                summary.associations += 1;
+                summary.studies.push(assoc.accession());
             }
         }
-    } else {
+        for summary in summaries.iter_mut().flatten() {
+            summary.studies.sort();
+            summary.studies.dedup();
+        }
+        summaries
+    }
+
+    /// Splits `genes` into those with at least one association, alongside the
+    /// labels of the terms they matched and the genes they were found in, and
+    /// those without.
+    pub fn partition(&self, genes: Vec<String>) -> GenePartition<'a> {
         let mut associated = Vec::with_capacity(genes.len());
         let mut not_associated = Vec::with_capacity(genes.len());
         for gene in genes {
-            let assoc = results
+            let associations = self.for_gene(&gene);
+            let labels = associations
                 .iter()
-                .any(|result| result.mapped_gene.contains(&gene));
-            if assoc {
-                associated.push(gene);
-            } else {
-                not_associated.push(gene);
+                .flat_map(|i| self.matched_terms(i))
+                .map(|i| i.label.as_str())
+                .collect::<BTreeSet<_>>();
+            let source = associations
+                .iter()
+                .filter_map(|i| self.gene_source(i, &gene))
+                .reduce(GeneSource::union);
+            match source {
+                Some(source) => associated.push((gene, labels, source)),
+                None => not_associated.push(gene),
             }
         }
-        if !associated.is_empty() {
-            println!("  ASSOCIATED:");
-            if csv {
-                println!(
-                    "    {}",
-                    associated.into_iter().collect::<Vec<_>>().join(",")
-                );
-            } else {
-                for gene in associated {
-                    println!("    {gene}");
-                }
-            }
+        GenePartition {
+            associated,
+            not_associated,
         }
-        if !not_associated.is_empty() {
-            println!("  NOT ASSOCIATED:");
-            if csv {
-                println!(
-                    "    {}",
-                    not_associated.into_iter().collect::<Vec<_>>().join(",")
-                );
-            } else {
-                for gene in not_associated {
-                    println!("    {gene}");
-                }
-            }
+    }
+}
+
+/// The associations of a trait with one gene, see [`TraitMatches::summarize`].
+#[derive(Debug)]
+pub struct GeneSummary {
+    pub best_p_value: f64,
+    pub associations: usize,
+    // sorted accession IDs
+    pub studies: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct GenePartition<'a> {
+    pub associated: Vec<(String, BTreeSet<&'a str>, GeneSource)>,
+    pub not_associated: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct GeneMatches<'a, A> {
+    pub gene: String,
+    // the symbols `gene` was given as, see `Filter::aliases`
+    pub aliases: Vec<String>,
+    // whether flanking genes count, see `Filter::flanking`
+    pub flanking: bool,
+    // which genes count, see `Filter::gene_source`
+    pub gene_source: GeneSource,
+    pub associations: Vec<&'a A>,
+}
+
+impl<A: AssociationRecord> GeneMatches<'_, A> {
+    /// Which searched genes of `assoc` list the queried gene.
+    pub fn gene_source(&self, assoc: &A) -> Option<GeneSource> {
+        let symbols = std::iter::once(&self.gene).chain(&self.aliases);
+        gene_source(
+            assoc,
+            symbols.map(String::as_str),
+            self.gene_source,
+            self.flanking,
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct VariantMatches<'a, A> {
+    pub variant: String,
+    pub associations: Vec<&'a A>,
+}
+
+#[derive(Debug)]
+pub struct RegionMatches<'a, A> {
+    pub region: GenomicRegion,
+    pub associations: Vec<&'a A>,
+}
+
+pub fn query_az<'a>(
+    associations: &'a [AzAssociation],
+    term: &str,
+    datasets: &[AzDataset],
+    threshold: f64,
+) -> Vec<&'a AzAssociation> {
+    associations
+        .par_iter()
+        .filter(|result| {
+            (datasets.is_empty() || datasets.contains(&result.dataset))
+                && result.is_significant(threshold)
+                && result.is_associated_with(term)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::data::tests::association;
+
+    fn efo(id: &str, label: &str, synonyms: &[&str]) -> Efo {
+        Efo {
+            id: TermId::parse(id).unwrap(),
+            label: label.to_string(),
+            parents: Vec::new(),
+            relations: Vec::new(),
+            children: HashSet::new(),
+            synonyms: synonyms.iter().map(|i| i.to_string()).collect(),
         }
     }
+
+    fn efos() -> Vec<Efo> {
+        vec![
+            efo("EFO_0000001", "THYROID DISEASE", &[]),
+            efo("EFO_0000002", "THYROID CANCER", &["THYROID CARCINOMA"]),
+            efo("EFO_0004705", "HYPOTHYROIDISM", &["UNDERACTIVE THYROID"]),
+            efo("EFO_0000003", "HEIGHT", &[]),
+        ]
+    }
+
+    fn labels<'a>(matches: &[EfoMatch<'a>]) -> Vec<&'a str> {
+        matches.iter().map(|i| i.efo.label.as_str()).collect()
+    }
+
+    #[test]
+    fn search_efo_exact_first() {
+        let efos = efos();
+        let matches = search_efo(&efos, "THYROID DISEASE", 10);
+        assert_eq!(matches[0].efo.label, "THYROID DISEASE");
+        assert_eq!(matches[0].score, 1.0);
+        assert!(matches[1..].iter().all(|i| i.score < 1.0));
+        assert!(!labels(&matches).contains(&"HEIGHT"));
+    }
+
+    #[test]
+    fn search_efo_partial_and_typo() {
+        let efos = efos();
+        assert_eq!(
+            search_efo(&efos, "THYROID DIS", 10)[0].efo.label,
+            "THYROID DISEASE"
+        );
+        assert_eq!(
+            search_efo(&efos, "HYPOTHYRODISM", 10)[0].efo.label,
+            "HYPOTHYROIDISM"
+        );
+    }
+
+    #[test]
+    fn search_efo_synonym() {
+        let efos = efos();
+        let matches = search_efo(&efos, "THYROID CARCINOMA", 10);
+        assert_eq!(matches[0].efo.label, "THYROID CANCER");
+        assert_eq!(matches[0].name, "THYROID CARCINOMA");
+        assert!(matches[0].is_synonym());
+    }
+
+    #[test]
+    fn search_efo_limit_and_empty() {
+        let efos = efos();
+        assert_eq!(search_efo(&efos, "THYROID", 2).len(), 2);
+        assert!(search_efo(&efos, " - ", 10).is_empty());
+        assert!(search_efo(&efos, "ZZZZQQQ", 10).is_empty());
+    }
+
+    #[test]
+    fn summarize_genes() {
+        let hypothyroidism = efo("EFO_0004705", "HYPOTHYROIDISM", &[]);
+        let terms = HashMap::from([(hypothyroidism.id, &hypothyroidism)]);
+        let mut associations = vec![
+            association(&["EFO_0004705"], &["TSHR"], &["TSHR"]),
+            association(&["EFO_0004705"], &["TSHR"], &[]),
+            association(&["EFO_0004705"], &["PARK2"], &[]),
+            // only reported, which is not searched
+            association(&["EFO_0004705"], &[], &["TSHR"]),
+            // another trait
+            association(&["EFO_0000003"], &["TSHR"], &[]),
+        ];
+        associations[1].p_value = 1e-12;
+        associations[2].accession_id = 2;
+        for i in &mut associations[3..] {
+            i.p_value = 1e-20;
+        }
+        let mut filter = Filter::default();
+        filter
+            .aliases
+            .insert("PRKN".to_string(), vec!["PARK2".to_string()]);
+        let matches = query_trait(&associations, &hypothyroidism, terms, &filter);
+
+        let genes = ["TSHR", "PRKN", "NOPE"].map(String::from);
+        let summaries = matches.summarize(&genes);
+        let tshr = summaries[0].as_ref().unwrap();
+        assert_eq!((tshr.best_p_value, tshr.associations), (1e-12, 2));
+        assert_eq!(tshr.studies, ["GCST000001"]);
+        let prkn = summaries[1].as_ref().unwrap();
+        assert_eq!((prkn.best_p_value, prkn.associations), (1e-10, 1));
+        assert_eq!(prkn.studies, ["GCST000002"]);
+        assert!(summaries[2].is_none());
+    }
 }